@@ -0,0 +1,622 @@
+//! Helpers for decoding untrusted msgpack input without panicking,
+//! and for measuring and hashing the encoded output
+
+use std::io::{self, Read, Write};
+
+use errors::{ErrorKind, Result};
+use options::{DecodeLimits, DecodeOptions};
+use ring;
+
+/// Reads a msgpack array header and checks that it has exactly `expected` items
+pub fn read_array_len_exact<R>(buffer: &mut R, expected: u32, options: &DecodeOptions) -> Result<()>
+    where R: Read
+{
+    let found = read_array_len(buffer, options)?;
+    if found != expected {
+        bail!(ErrorKind::UnexpectedArrayLength(expected, found));
+    }
+    Ok(())
+}
+
+/// Reads a msgpack array header and returns the number of items
+pub fn read_array_len<R>(buffer: &mut R, options: &DecodeOptions) -> Result<u32>
+    where R: Read
+{
+    if !options.strict {
+        return Ok(::rmp::decode::read_array_len(buffer)?);
+    }
+    let marker = read_byte(buffer)?;
+    let length = match marker {
+        0x90..=0x9f => u64::from(marker & 0x0f),
+        0xdc => read_big_endian(buffer, 2)?,
+        0xdd => read_big_endian(buffer, 4)?,
+        _ => bail!(ErrorKind::UnexpectedMarker(marker)),
+    };
+    check_canonical(marker, array_header_len(length as usize) == marker_len(marker))?;
+    Ok(length as u32)
+}
+
+/// Reads a msgpack binary header and returns the length of the data
+pub fn read_bin_len<R>(buffer: &mut R, options: &DecodeOptions) -> Result<u32>
+    where R: Read
+{
+    if !options.strict {
+        return Ok(::rmp::decode::read_bin_len(buffer)?);
+    }
+    let marker = read_byte(buffer)?;
+    let length = match marker {
+        0xc4 => read_big_endian(buffer, 1)?,
+        0xc5 => read_big_endian(buffer, 2)?,
+        0xc6 => read_big_endian(buffer, 4)?,
+        _ => bail!(ErrorKind::UnexpectedMarker(marker)),
+    };
+    check_canonical(marker, bin_len(length as usize) - length as usize == marker_len(marker))?;
+    Ok(length as u32)
+}
+
+/// Reads a msgpack string header and returns the length of the UTF-8 data
+pub fn read_str_len<R>(buffer: &mut R, options: &DecodeOptions) -> Result<u32>
+    where R: Read
+{
+    if !options.strict {
+        return Ok(::rmp::decode::read_str_len(buffer)?);
+    }
+    let marker = read_byte(buffer)?;
+    let length = match marker {
+        0xa0..=0xbf => u64::from(marker & 0x1f),
+        0xd9 => read_big_endian(buffer, 1)?,
+        0xda => read_big_endian(buffer, 2)?,
+        0xdb => read_big_endian(buffer, 4)?,
+        _ => bail!(ErrorKind::UnexpectedMarker(marker)),
+    };
+    check_canonical(marker, str_len(length as usize) - length as usize == marker_len(marker))?;
+    Ok(length as u32)
+}
+
+/// A variable length field, its length is limited by the [`DecodeLimits`]
+///
+/// [`DecodeLimits`]: ../options/struct.DecodeLimits.html
+#[derive(Debug, Clone, Copy)]
+pub enum Field {
+    Message,
+    ContentType,
+    Content,
+}
+
+impl Field {
+    fn limit(self, limits: &DecodeLimits) -> u32
+    {
+        match self {
+            Field::Message => limits.max_message_size,
+            Field::ContentType => limits.max_content_type_len,
+            Field::Content => limits.max_content_len,
+        }
+    }
+
+    fn name(self) -> &'static str
+    {
+        match self {
+            Field::Message => "message",
+            Field::ContentType => "content type",
+            Field::Content => "content",
+        }
+    }
+}
+
+/// Reads the header of a msgpack binary holding `field` and checks its length
+pub fn read_field_len<R>(buffer: &mut R, options: &DecodeOptions, field: Field) -> Result<u32>
+    where R: Read
+{
+    let length = read_bin_len(buffer, options)?;
+    check_field_len(u64::from(length), options, field)?;
+    Ok(length)
+}
+
+/// Checks the `length` of `field` against its limit in `options`
+pub fn check_field_len(length: u64, options: &DecodeOptions, field: Field) -> Result<()>
+{
+    let limit = field.limit(&options.limits);
+    if length > u64::from(limit) {
+        bail!(ErrorKind::LimitExceeded(field.name().to_string(), length, u64::from(limit)));
+    }
+    Ok(())
+}
+
+/// Reads a msgpack binary holding `field`
+pub fn read_bin<R>(buffer: &mut R, options: &DecodeOptions, field: Field) -> Result<Vec<u8>>
+    where R: Read
+{
+    let length = read_field_len(buffer, options, field)?;
+    read_data(buffer, length)
+}
+
+/// Reads a msgpack binary holding `field`, borrowing it from the input
+pub fn read_bin_ref<'a>(bytes: &mut &'a [u8], options: &DecodeOptions, field: Field) -> Result<&'a [u8]>
+{
+    let length = read_field_len(bytes, options, field)?;
+    take(bytes, length as usize)
+}
+
+/// Splits the first `length` bytes off the input
+pub fn take<'a>(bytes: &mut &'a [u8], length: usize) -> Result<&'a [u8]>
+{
+    if bytes.len() < length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "msgpack data is truncated").into());
+    }
+    let (data, rest) = bytes.split_at(length);
+    *bytes = rest;
+    Ok(data)
+}
+
+/// Reads a non-negative msgpack integer
+///
+/// In strict mode only the unsigned markers are accepted.
+pub fn read_uint<R>(buffer: &mut R, options: &DecodeOptions) -> Result<u64>
+    where R: Read
+{
+    if !options.strict {
+        return Ok(::rmp::decode::read_int(buffer)?);
+    }
+    let marker = read_byte(buffer)?;
+    let value = match marker {
+        0x00..=0x7f => u64::from(marker),
+        0xcc => read_big_endian(buffer, 1)?,
+        0xcd => read_big_endian(buffer, 2)?,
+        0xce => read_big_endian(buffer, 4)?,
+        0xcf => read_big_endian(buffer, 8)?,
+        _ => bail!(ErrorKind::UnexpectedMarker(marker)),
+    };
+    check_canonical(marker, uint_len(value) == marker_len(marker))?;
+    Ok(value)
+}
+
+/// Checks that the whole input was consumed, if the `options` require it
+pub fn check_consumed(remaining: &[u8], options: &DecodeOptions) -> Result<()>
+{
+    if options.strict && !remaining.is_empty() {
+        bail!(ErrorKind::TrailingBytes(remaining.len() as u64));
+    }
+    Ok(())
+}
+
+fn check_canonical(marker: u8, is_shortest: bool) -> Result<()>
+{
+    if !is_shortest {
+        bail!(ErrorKind::NonCanonicalEncoding(marker));
+    }
+    Ok(())
+}
+
+/// Size of the marker and the length or value following it
+fn marker_len(marker: u8) -> usize
+{
+    match marker {
+        0x00..=0x7f | 0x90..=0x9f | 0xa0..=0xbf => 1,
+        0xc4 | 0xcc | 0xd9 => 2,
+        0xc5 | 0xcd | 0xda | 0xdc => 3,
+        0xc6 | 0xce | 0xdb | 0xdd => 5,
+        _ => 9,
+    }
+}
+
+fn read_byte<R>(buffer: &mut R) -> Result<u8>
+    where R: Read
+{
+    let mut byte = [0u8; 1];
+    buffer.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_big_endian<R>(buffer: &mut R, size: usize) -> Result<u64>
+    where R: Read
+{
+    let mut bytes = [0u8; 8];
+    buffer.read_exact(&mut bytes[..size])?;
+    Ok(bytes[..size].iter().fold(0u64, |value, &byte| (value << 8) | u64::from(byte)))
+}
+
+/// Reads `length` bytes of data following a msgpack header
+///
+/// The length prefix comes from the input, so the buffer grows
+/// with the data actually read instead of being allocated upfront.
+pub fn read_data<R>(buffer: &mut R, length: u32) -> Result<Vec<u8>>
+    where R: Read
+{
+    let mut data = Vec::new();
+    buffer.take(u64::from(length)).read_to_end(&mut data)?;
+    if data.len() != length as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "msgpack data is truncated").into());
+    }
+    Ok(data)
+}
+
+/// Encoded size of a msgpack array header
+pub fn array_header_len(items: usize) -> usize
+{
+    match items {
+        0..=15 => 1,
+        16..=0xffff => 3,
+        _ => 5,
+    }
+}
+
+/// Encoded size of a msgpack binary, including its header
+pub fn bin_len(data_length: usize) -> usize
+{
+    let header = match data_length {
+        0..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    header + data_length
+}
+
+/// Encoded size of a msgpack string, including its header
+pub fn str_len(data_length: usize) -> usize
+{
+    let header = match data_length {
+        0..=31 => 1,
+        32..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    header + data_length
+}
+
+/// Encoded size of a msgpack unsigned integer
+pub fn uint_len(value: u64) -> usize
+{
+    match value {
+        0..=0x7f => 1,
+        0x80..=0xff => 2,
+        0x100..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Counts the bytes written through it
+pub struct CountingWriter<'a, W: 'a + ?Sized> {
+    inner: &'a mut W,
+    count: usize,
+}
+
+impl<'a, W> CountingWriter<'a, W>
+    where W: 'a + Write + ?Sized
+{
+    pub fn new(inner: &'a mut W) -> CountingWriter<'a, W>
+    {
+        CountingWriter { inner, count: 0 }
+    }
+
+    pub fn count(&self) -> usize
+    {
+        self.count
+    }
+}
+
+impl<'a, W> Write for CountingWriter<'a, W>
+    where W: 'a + Write + ?Sized
+{
+    fn write(&mut self, data: &[u8]) -> io::Result<usize>
+    {
+        let written = self.inner.write(data)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        self.inner.flush()
+    }
+}
+
+/// Computes the SHA-512 digest of the bytes written through it,
+/// while passing them to the inner writer
+pub struct DigestWriter<W> {
+    inner: W,
+    context: ring::digest::Context,
+}
+
+impl<W> DigestWriter<W>
+    where W: Write
+{
+    pub fn new(inner: W) -> DigestWriter<W>
+    {
+        DigestWriter {
+            inner,
+            context: ring::digest::Context::new(&ring::digest::SHA512),
+        }
+    }
+
+    pub fn finish(self) -> (W, ring::digest::Digest)
+    {
+        (self.inner, self.context.finish())
+    }
+}
+
+impl<W> Write for DigestWriter<W>
+    where W: Write
+{
+    fn write(&mut self, data: &[u8]) -> io::Result<usize>
+    {
+        let written = self.inner.write(data)?;
+        self.context.update(&data[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        self.inner.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compression::Compression;
+    use frame::Frame;
+    use message::{ContentType, Hash, Message, PubKey, Stamp};
+    use signature::Signature;
+    use view::{FrameRefs, MessageRef};
+
+    #[test]
+    fn array_with_unexpected_length_is_rejected()
+    {
+        let result = read_array_len_exact(&mut io::Cursor::new(vec![0x92]), 3, &DecodeOptions::default());
+        match result {
+            Err(::errors::Error(ErrorKind::UnexpectedArrayLength(3, 2), _)) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_binary_is_rejected_without_allocating_its_declared_length()
+    {
+        // bin32 declaring 4 GiB, followed by 2 bytes
+        let input = vec![0xc6, 0xff, 0xff, 0xff, 0xff, 0x01, 0x02];
+        let options = DecodeOptions { limits: DecodeLimits::unlimited(), ..DecodeOptions::default() };
+        assert!(read_bin(&mut io::Cursor::new(input), &options, Field::Content).is_err());
+    }
+
+    #[test]
+    fn length_above_the_limit_is_rejected_before_reading()
+    {
+        // bin32 declaring 4 GiB, without the data
+        let input = [0xc6, 0xff, 0xff, 0xff, 0xff];
+        let limit = DecodeLimits::default().max_content_len;
+        match read_bin(&mut &input[..], &DecodeOptions::default(), Field::Content) {
+            Err(::errors::Error(ErrorKind::LimitExceeded(ref field, 0xffff_ffff, found_limit), _)) => {
+                assert_eq!(field, "content");
+                assert_eq!(found_limit, u64::from(limit));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let options = DecodeOptions {
+            limits: DecodeLimits { max_content_type_len: 3, ..DecodeLimits::default() },
+            ..DecodeOptions::default()
+        };
+        assert!(read_bin(&mut &[0xc4, 0x03, 1, 2, 3][..], &options, Field::ContentType).is_ok());
+        assert!(read_bin(&mut &[0xc4, 0x04, 1, 2, 3, 4][..], &options, Field::ContentType).is_err());
+    }
+
+    #[test]
+    fn canonical_encodings_are_accepted_in_strict_mode()
+    {
+        use rmp::encode;
+        let strict = DecodeOptions { limits: DecodeLimits::unlimited(), ..DecodeOptions::strict() };
+        for &length in &[0usize, 15, 16, 255, 256, 0xffff, 0x10000] {
+            let mut buffer = Vec::new();
+            encode::write_array_len(&mut buffer, length as u32).unwrap();
+            assert_eq!(read_array_len(&mut &buffer[..], &strict).unwrap(), length as u32);
+
+            let mut buffer = Vec::new();
+            encode::write_bin(&mut buffer, &vec![7u8; length]).unwrap();
+            assert_eq!(read_bin(&mut &buffer[..], &strict, Field::Content).unwrap().len(), length);
+
+            let mut buffer = Vec::new();
+            encode::write_str(&mut buffer, &"a".repeat(length)).unwrap();
+            assert_eq!(read_str_len(&mut &buffer[..], &strict).unwrap(), length as u32);
+        }
+        for &value in &[0u64, 0x7f, 0x80, 0xff, 0x100, 0xffff, 0x10000, 0xffff_ffff, 0x1_0000_0000, !0u64] {
+            let mut buffer = Vec::new();
+            encode::write_uint(&mut buffer, value).unwrap();
+            assert_eq!(read_uint(&mut &buffer[..], &strict).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn non_minimal_encodings_are_rejected_in_strict_mode()
+    {
+        let non_minimal: Vec<Vec<u8>> = vec![
+            vec![0xdc, 0x00, 0x03],               // array16 with 3 items
+            vec![0xdd, 0x00, 0x00, 0x00, 0x10],   // array32 with 16 items
+            vec![0xc5, 0x00, 0x01, 0x2a],         // bin16 with 1 byte
+            vec![0xc6, 0x00, 0x00, 0x00, 0x01, 0x2a], // bin32 with 1 byte
+            vec![0xd9, 0x01, 0x61],               // str8 with 1 byte
+            vec![0xda, 0x00, 0x20],               // str16 with 32 bytes
+            vec![0xcc, 0x01],                     // uint8 for 1
+            vec![0xcd, 0x00, 0xff],               // uint16 for 255
+            vec![0xcf, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff], // uint64 for a u32
+        ];
+        let strict = DecodeOptions::strict();
+        let lenient = DecodeOptions::default();
+        for input in non_minimal {
+            let (strict_result, lenient_result) = match input[0] {
+                0xdc | 0xdd => (read_array_len(&mut &input[..], &strict).is_ok(), read_array_len(&mut &input[..], &lenient).is_ok()),
+                0xc5 | 0xc6 => (read_bin(&mut &input[..], &strict, Field::Content).is_ok(),
+                                read_bin(&mut &input[..], &lenient, Field::Content).is_ok()),
+                0xd9 | 0xda => (read_str_len(&mut &input[..], &strict).is_ok(), read_str_len(&mut &input[..], &lenient).is_ok()),
+                _ => (read_uint(&mut &input[..], &strict).is_ok(), read_uint(&mut &input[..], &lenient).is_ok()),
+            };
+            assert!(!strict_result, "accepted {:?}", input);
+            assert!(lenient_result, "rejected {:?}", input);
+        }
+    }
+
+    #[test]
+    fn signed_integer_is_rejected_in_strict_mode()
+    {
+        // int8 1
+        let input = [0xd0, 0x01];
+        match read_uint(&mut &input[..], &DecodeOptions::strict()) {
+            Err(::errors::Error(ErrorKind::UnexpectedMarker(0xd0), _)) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(read_uint(&mut &input[..], &DecodeOptions::default()).unwrap(), 1);
+    }
+
+    #[test]
+    fn encoded_lengths_match_the_msgpack_encoder()
+    {
+        use rmp::encode;
+        for &length in &[0usize, 1, 15, 16, 255, 256, 0xffff, 0x10000] {
+            let mut buffer = Vec::new();
+            encode::write_array_len(&mut buffer, length as u32).unwrap();
+            assert_eq!(buffer.len(), array_header_len(length), "array of {}", length);
+
+            let mut buffer = Vec::new();
+            encode::write_bin(&mut buffer, &vec![0u8; length]).unwrap();
+            assert_eq!(buffer.len(), bin_len(length), "bin of {}", length);
+
+            let mut buffer = Vec::new();
+            encode::write_str(&mut buffer, &"a".repeat(length)).unwrap();
+            assert_eq!(buffer.len(), str_len(length), "str of {}", length);
+        }
+        for &value in &[0u64, 0x7f, 0x80, 0xff, 0x100, 0xffff, 0x10000, 0xffff_ffff, 0x1_0000_0000, !0u64] {
+            let mut buffer = Vec::new();
+            encode::write_uint(&mut buffer, value).unwrap();
+            assert_eq!(buffer.len(), uint_len(value), "uint {}", value);
+        }
+    }
+
+    #[test]
+    fn digest_writer_hashes_and_passes_through()
+    {
+        let mut writer = DigestWriter::new(Vec::new());
+        writer.write_all(b"kutyus").unwrap();
+        let (buffer, digest) = writer.finish();
+
+        assert_eq!(buffer, b"kutyus".to_vec());
+        assert_eq!(digest.as_ref(), ring::digest::digest(&ring::digest::SHA512, b"kutyus").as_ref());
+    }
+
+    /// Feeds truncated, mutated and random inputs to every decoder.
+    /// None of them may panic; errors are fine.
+    #[test]
+    fn decoders_never_panic()
+    {
+        for input in corpus() {
+            if let Ok(frame) = Frame::read(&mut io::Cursor::new(&input)) {
+                let _ = frame.decode_message();
+            }
+            let _ = Message::read(&mut io::Cursor::new(&input));
+            let _ = Hash::read(&mut io::Cursor::new(&input));
+            let _ = ContentType::read(&mut io::Cursor::new(&input));
+
+            let _ = Frame::decode(&input, &DecodeOptions::strict());
+            let _ = Message::decode(&input, &DecodeOptions::strict());
+            for frame in FrameRefs::new(&input).flatten() {
+                let _ = frame.decode_message(&DecodeOptions::strict());
+            }
+            let _ = MessageRef::decode(&input, &DecodeOptions::default());
+        }
+    }
+
+    fn corpus() -> Vec<Vec<u8>>
+    {
+        let mut corpus = Vec::new();
+        let mut random = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for valid in valid_inputs() {
+            // every truncation
+            for length in 0..valid.len() {
+                corpus.push(valid[..length].to_vec());
+            }
+            // every single-byte mutation with some interesting values
+            for position in 0..valid.len() {
+                for &value in &[0x00, 0x01, 0x7f, 0x80, 0x90, 0x93, 0x94, 0xc0, 0xc4, 0xc5, 0xc6, 0xce, 0xcf, 0xdc, 0xdd, 0xff] {
+                    let mut mutated = valid.clone();
+                    mutated[position] = value;
+                    corpus.push(mutated);
+                }
+            }
+            // random bit flips
+            for _ in 0..200 {
+                let mut mutated = valid.clone();
+                let position = random.next() as usize % mutated.len();
+                mutated[position] ^= 1 << (random.next() % 8);
+                corpus.push(mutated);
+            }
+        }
+
+        // random garbage
+        for _ in 0..500 {
+            let length = random.next() as usize % 128;
+            corpus.push((0..length).map(|_| random.next() as u8).collect());
+        }
+
+        corpus
+    }
+
+    fn valid_inputs() -> Vec<Vec<u8>>
+    {
+        let root = Message {
+            author: PubKey([1u8; 32]),
+            parent: None,
+            stamp: None,
+            compression: Compression::None,
+            content_type: ContentType::Blob,
+            content: vec![1u8, 2u8, 3u8],
+        };
+        let child = Message {
+            author: PubKey([2u8; 32]),
+            parent: Some(Hash([3u8; 64])),
+            stamp: Some(Stamp { sequence: 1, timestamp: 1_500_000_000_000 }),
+            compression: Compression::None,
+            content_type: ContentType::Custom(b"custom".to_vec()),
+            content: vec![4u8; 300],
+        };
+        let compressed = Message {
+            author: PubKey([2u8; 32]),
+            parent: Some(Hash([4u8; 64])),
+            stamp: Some(Stamp { sequence: 2, timestamp: 1_500_000_000_001 }),
+            compression: Compression::Deflate,
+            content_type: ContentType::Custom(b"custom".to_vec()),
+            content: Compression::Deflate.compress(&[6u8; 300]).into_owned(),
+        };
+
+        let mut inputs = Vec::new();
+        for message in &[root, child, compressed] {
+            let mut message_buffer = Vec::new();
+            message.write(&mut message_buffer).unwrap();
+
+            let frame = Frame {
+                version: message.version(),
+                message: message_buffer.clone(),
+                signature: Signature([5u8; 64]),
+            };
+            let mut frame_buffer = Vec::new();
+            frame.write(&mut frame_buffer).unwrap();
+
+            inputs.push(message_buffer);
+            inputs.push(frame_buffer);
+        }
+        inputs
+    }
+
+    /// Deterministic pseudo-random numbers, so failures are reproducible
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64
+        {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Optional compression of message contents
+//!
+//! The compression method is an item of version 3 messages, so it is covered by
+//! the signature, and a reader can restore the content of any content type
+//! without knowing about it, see [`Message::decompressed_content`].
+//!
+//! A small compressed content can expand to gigabytes, so decompression stops at
+//! [`DecodeLimits::max_decompressed_len`] instead of allocating the whole output.
+//!
+//! [`Message::decompressed_content`]: ../message/struct.Message.html#method.decompressed_content
+//! [`DecodeLimits::max_decompressed_len`]: ../options/struct.DecodeLimits.html#structfield.max_decompressed_len
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use miniz_oxide::{deflate, inflate};
+
+use errors::{Error, ErrorKind, Result};
+use options::DecodeLimits;
+
+/// Compression level of the deflate encoder, the zlib default
+const DEFLATE_LEVEL: u8 = 6;
+
+/// Compression method of the content of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Compression {
+    /// The content is stored as is
+    #[default]
+    None,
+    /// The content is a raw deflate stream (RFC 1951)
+    Deflate,
+}
+
+impl Compression {
+    /// Code of the method in version 3 messages, `None` is never written
+    pub(crate) fn code(self) -> u64
+    {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    /// The method of a code read from a version 3 message
+    pub(crate) fn from_code(code: u64) -> Result<Compression>
+    {
+        match code {
+            1 => Ok(Compression::Deflate),
+            _ => bail!(ErrorKind::UnknownCompression(code)),
+        }
+    }
+
+    /// Compresses the content
+    pub fn compress<'a>(self, content: &'a [u8]) -> Cow<'a, [u8]>
+    {
+        match self {
+            Compression::None => Cow::Borrowed(content),
+            Compression::Deflate => Cow::Owned(deflate::compress_to_vec(content, DEFLATE_LEVEL)),
+        }
+    }
+
+    /// Decompresses the content, failing if the result is longer than
+    /// [`DecodeLimits::max_decompressed_len`]
+    ///
+    /// [`DecodeLimits::max_decompressed_len`]: ../options/struct.DecodeLimits.html#structfield.max_decompressed_len
+    pub fn decompress<'a>(self, content: &'a [u8], limits: &DecodeLimits) -> Result<Cow<'a, [u8]>>
+    {
+        match self {
+            Compression::None => Ok(Cow::Borrowed(content)),
+            Compression::Deflate => {
+                let limit = limits.max_decompressed_len;
+                inflate::decompress_to_vec_with_limit(content, limit as usize)
+                    .map(Cow::Owned)
+                    .map_err(|e| match e.status {
+                        inflate::TINFLStatus::HasMoreOutput => ErrorKind::DecompressionLimitExceeded(u64::from(limit)).into(),
+                        _ => Error::from(ErrorKind::CorruptCompressedContent(e.to_string())),
+                    })
+            },
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+        })
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Compression> {
+        match text {
+            "none" => Ok(Compression::None),
+            "deflate" => Ok(Compression::Deflate),
+            _ => bail!("unknown compression {:?}", text),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::Error;
+
+    #[test]
+    fn deflate_round_trip()
+    {
+        let content = b"hello hello hello hello hello hello hello".to_vec();
+        let compressed = Compression::Deflate.compress(&content);
+        assert!(compressed.len() < content.len());
+
+        let decompressed = Compression::Deflate.decompress(&compressed, &DecodeLimits::default()).unwrap();
+        assert_eq!(decompressed.as_ref(), &content[..]);
+    }
+
+    #[test]
+    fn uncompressed_content_is_borrowed()
+    {
+        let content = [1u8, 2u8];
+        match Compression::None.decompress(&content, &DecodeLimits::default()).unwrap() {
+            Cow::Borrowed(bytes) => assert_eq!(bytes, &content[..]),
+            Cow::Owned(_) => panic!("content was copied"),
+        }
+    }
+
+    #[test]
+    fn decompression_stops_at_the_limit()
+    {
+        // 1 MiB of zeros compresses to about a kilobyte
+        let bomb = Compression::Deflate.compress(&vec![0u8; 1024 * 1024]).into_owned();
+        let limits = DecodeLimits { max_decompressed_len: 1024, ..DecodeLimits::default() };
+
+        match Compression::Deflate.decompress(&bomb, &limits) {
+            Err(Error(ErrorKind::DecompressionLimitExceeded(1024), _)) => {},
+            other => panic!("Unexpected result: {:?}", other.map(|c| c.len())),
+        }
+        assert!(Compression::Deflate.decompress(&bomb, &DecodeLimits::default()).is_ok());
+    }
+
+    #[test]
+    fn corrupt_stream_is_rejected()
+    {
+        match Compression::Deflate.decompress(&[0xff, 0xff, 0xff], &DecodeLimits::default()) {
+            Err(Error(ErrorKind::CorruptCompressedContent(_), _)) => {},
+            other => panic!("Unexpected result: {:?}", other.map(|c| c.len())),
+        }
+    }
+
+    #[test]
+    fn only_real_methods_have_a_code()
+    {
+        assert_eq!(Compression::from_code(Compression::Deflate.code()).unwrap(), Compression::Deflate);
+        assert!(Compression::from_code(0).is_err());
+        assert!(Compression::from_code(2).is_err());
+    }
+
+    #[test]
+    fn compression_can_be_displayed_and_parsed()
+    {
+        for &compression in &[Compression::None, Compression::Deflate] {
+            assert_eq!(compression.to_string().parse::<Compression>().unwrap(), compression);
+        }
+        assert!("zstd".parse::<Compression>().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! The `about` content type: an author describing their own feed
+//!
+//! The content is a msgpack array of three optional items, each encoded as an
+//! array of zero or one item:
+//!
+//! ```text
+//! [
+//!   name: [] or [str],               display name of the author
+//!   description: [] or [str],        a few words about the feed
+//!   avatar: [] or [bin 64]           hash of the avatar image blob
+//! ]
+//! ```
+//!
+//! An `about` message updates only the fields it sets, the effective profile of
+//! an author takes every field from the latest message of the feed setting it.
+
+use rmp::encode;
+
+use errors::Result;
+use codec;
+use message::Hash;
+use options::DecodeOptions;
+use super::{ContentCodec, decode_payload, read_hash, read_option, read_str, write_option};
+
+/// Maximum number of characters in a display name
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Maximum number of characters in a description
+pub const MAX_DESCRIPTION_LEN: usize = 1024;
+
+/// An update of the profile of the author, see the [module documentation](index.html)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct About {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub avatar: Option<Hash>,
+}
+
+impl About {
+    /// An update setting only the display name
+    pub fn name<S>(name: S) -> About
+        where S: Into<String>
+    {
+        About { name: Some(name.into()), ..About::default() }
+    }
+}
+
+impl ContentCodec for About {
+    const CONTENT_TYPE: &'static [u8] = b"about";
+
+    fn encode(&self) -> Result<Vec<u8>>
+    {
+        let mut buffer = Vec::new();
+        encode::write_array_len(&mut buffer, 3)?;
+        write_option(&mut buffer, self.name.as_deref(), |buffer, text| {
+            encode::write_str(buffer, text)?;
+            Ok(())
+        })?;
+        write_option(&mut buffer, self.description.as_deref(), |buffer, text| {
+            encode::write_str(buffer, text)?;
+            Ok(())
+        })?;
+        write_option(&mut buffer, self.avatar.as_ref(), |buffer, hash| {
+            encode::write_bin(buffer, &hash.0)?;
+            Ok(())
+        })?;
+        Ok(buffer)
+    }
+
+    fn decode(content: &[u8]) -> Result<About>
+    {
+        decode_payload(content, |buffer| {
+            codec::read_array_len_exact(buffer, 3, &DecodeOptions::strict())?;
+            Ok(About {
+                name: read_option(buffer, read_str)?,
+                description: read_option(buffer, read_str)?,
+                avatar: read_option(buffer, read_hash)?,
+            })
+        })
+    }
+
+    fn validate(&self) -> Result<()>
+    {
+        if self.name.is_none() && self.description.is_none() && self.avatar.is_none() {
+            bail!("an about message must set at least one field");
+        }
+        if let Some(ref name) = self.name {
+            if name.trim().is_empty() {
+                bail!("the name can not be empty");
+            }
+            if name.chars().count() > MAX_NAME_LEN {
+                bail!("the name is longer than {} characters", MAX_NAME_LEN);
+            }
+            if name.chars().any(char::is_control) {
+                bail!("the name can not contain control characters");
+            }
+        }
+        if let Some(ref description) = self.description {
+            if description.chars().count() > MAX_DESCRIPTION_LEN {
+                bail!("the description is longer than {} characters", MAX_DESCRIPTION_LEN);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use content;
+
+    #[test]
+    fn about_is_encoded_and_decoded()
+    {
+        let about = About {
+            name: Some("Bob".to_string()),
+            description: Some("posts about dogs".to_string()),
+            avatar: Some(Hash([5u8; 64])),
+        };
+        let (_, content) = content::encode(&about).unwrap();
+        assert_eq!(content::decode::<About>(b"about", &content).unwrap(), about);
+    }
+
+    #[test]
+    fn unset_fields_are_empty_arrays()
+    {
+        let content = About::name("Bob").encode().unwrap();
+        assert_eq!(content, [0x93, 0x91, 0xa3, b'B', b'o', b'b', 0x90, 0x90]);
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected()
+    {
+        assert!(content::encode(&About::default()).is_err());
+        assert!(content::encode(&About::name("")).is_err());
+        assert!(content::encode(&About::name("a\nb")).is_err());
+        assert!(content::encode(&About::name("x".repeat(MAX_NAME_LEN + 1))).is_err());
+        assert!(content::encode(&About::name("ű".repeat(MAX_NAME_LEN))).is_ok());
+    }
+
+    #[test]
+    fn option_with_two_items_is_rejected()
+    {
+        assert!(About::decode(&[0x93, 0x92, 0xa1, b'a', 0xa1, b'b', 0x90, 0x90]).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! The `blob-ref` content type: a reference to a blob stored outside the feed
+//!
+//! Large attachments are not embedded in messages, the message signs only the
+//! SHA-512 hash and the size of the blob. The content is a msgpack array:
+//!
+//! ```text
+//! [
+//!   hash: bin 64,                    SHA-512 of the blob
+//!   size: uint                       length of the blob in bytes
+//! ]
+//! ```
+//!
+//! Blobs fetched by a reference must be checked against it, see [`BlobHasher`].
+//!
+//! [`BlobHasher`]: struct.BlobHasher.html
+
+use std::io;
+
+use rmp::encode;
+
+use errors::Result;
+use codec;
+use message::Hash;
+use options::DecodeOptions;
+use super::{ContentCodec, decode_payload, read_hash};
+
+/// A reference to a blob, see the [module documentation](index.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobRef {
+    pub hash: Hash,
+    pub size: u64,
+}
+
+impl BlobRef {
+    /// The reference of the blob `bytes`
+    pub fn of_bytes(bytes: &[u8]) -> BlobRef
+    {
+        let mut hasher = BlobHasher::new(io::sink());
+        io::Write::write_all(&mut hasher, bytes).expect("writing to a sink does not fail");
+        hasher.finish().1
+    }
+}
+
+impl ContentCodec for BlobRef {
+    const CONTENT_TYPE: &'static [u8] = b"blob-ref";
+
+    fn encode(&self) -> Result<Vec<u8>>
+    {
+        let mut buffer = Vec::new();
+        encode::write_array_len(&mut buffer, 2)?;
+        encode::write_bin(&mut buffer, &self.hash.0)?;
+        encode::write_uint(&mut buffer, self.size)?;
+        Ok(buffer)
+    }
+
+    fn decode(content: &[u8]) -> Result<BlobRef>
+    {
+        decode_payload(content, |buffer| {
+            let options = DecodeOptions::strict();
+            codec::read_array_len_exact(buffer, 2, &options)?;
+            Ok(BlobRef {
+                hash: read_hash(buffer)?,
+                size: codec::read_uint(buffer, &options)?,
+            })
+        })
+    }
+}
+
+/// Computes the [`BlobRef`] of the bytes written through it, while passing them
+/// to the inner writer
+///
+/// [`BlobRef`]: struct.BlobRef.html
+pub struct BlobHasher<W> {
+    inner: codec::DigestWriter<W>,
+    size: u64,
+}
+
+impl<W> BlobHasher<W>
+    where W: io::Write
+{
+    pub fn new(inner: W) -> BlobHasher<W>
+    {
+        BlobHasher { inner: codec::DigestWriter::new(inner), size: 0 }
+    }
+
+    /// Returns the inner writer and the reference of the written bytes
+    pub fn finish(self) -> (W, BlobRef)
+    {
+        let (inner, digest) = self.inner.finish();
+        let mut hash = [0u8; 64];
+        hash.copy_from_slice(digest.as_ref());
+        (inner, BlobRef { hash: Hash(hash), size: self.size })
+    }
+}
+
+impl<W> io::Write for BlobHasher<W>
+    where W: io::Write
+{
+    fn write(&mut self, data: &[u8]) -> io::Result<usize>
+    {
+        let written = self.inner.write(data)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        self.inner.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use content;
+
+    #[test]
+    fn blob_ref_is_encoded_and_decoded()
+    {
+        let blob = BlobRef::of_bytes(b"attachment");
+        let (content_type, content) = content::encode(&blob).unwrap();
+        assert_eq!(content_type.as_bytes(), b"blob-ref");
+        assert_eq!(content::decode::<BlobRef>(b"blob-ref", &content).unwrap(), blob);
+    }
+
+    #[test]
+    fn hasher_passes_through_and_references_the_written_bytes()
+    {
+        let mut hasher = BlobHasher::new(Vec::new());
+        hasher.write_all(b"attach").unwrap();
+        hasher.write_all(b"ment").unwrap();
+        let (written, blob) = hasher.finish();
+
+        assert_eq!(written, b"attachment");
+        assert_eq!(blob, BlobRef::of_bytes(b"attachment"));
+        assert_eq!(blob.size, 10);
+        assert!(blob != BlobRef::of_bytes(b"attachmenT"));
+    }
+}
@@ -0,0 +1,125 @@
+//! The `contact` content type: the relation of the author to another author
+//!
+//! The content is a msgpack array of two items:
+//!
+//! ```text
+//! [
+//!   contact: bin 32,                 public key of the other author
+//!   relation: uint                   0: unfollow, 1: follow, 2: block
+//! ]
+//! ```
+//!
+//! A later `contact` message about the same author replaces the earlier relation,
+//! see [`SocialGraph`].
+//!
+//! [`SocialGraph`]: ../../graph/struct.SocialGraph.html
+
+use std::fmt;
+
+use rmp::encode;
+
+use errors::Result;
+use codec;
+use message::PubKey;
+use options::DecodeOptions;
+use super::{ContentCodec, decode_payload, read_pubkey};
+
+/// Relation of the author to the contact
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Relation {
+    /// Ends an earlier follow or block
+    Unfollow,
+    /// The author is interested in the feed of the contact
+    Follow,
+    /// The author does not want to see or replicate the feed of the contact
+    Block,
+}
+
+impl Relation {
+    fn code(self) -> u64
+    {
+        match self {
+            Relation::Unfollow => 0,
+            Relation::Follow => 1,
+            Relation::Block => 2,
+        }
+    }
+
+    fn from_code(code: u64) -> Result<Relation>
+    {
+        match code {
+            0 => Ok(Relation::Unfollow),
+            1 => Ok(Relation::Follow),
+            2 => Ok(Relation::Block),
+            _ => bail!("unknown relation {}", code),
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Relation::Unfollow => "unfollow",
+            Relation::Follow => "follow",
+            Relation::Block => "block",
+        })
+    }
+}
+
+/// A change of relation to another author, see the [module documentation](index.html)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub contact: PubKey,
+    pub relation: Relation,
+}
+
+impl ContentCodec for Contact {
+    const CONTENT_TYPE: &'static [u8] = b"contact";
+
+    fn encode(&self) -> Result<Vec<u8>>
+    {
+        let mut buffer = Vec::new();
+        encode::write_array_len(&mut buffer, 2)?;
+        encode::write_bin(&mut buffer, &self.contact.0)?;
+        encode::write_uint(&mut buffer, self.relation.code())?;
+        Ok(buffer)
+    }
+
+    fn decode(content: &[u8]) -> Result<Contact>
+    {
+        decode_payload(content, |buffer| {
+            let options = DecodeOptions::strict();
+            codec::read_array_len_exact(buffer, 2, &options)?;
+            Ok(Contact {
+                contact: read_pubkey(buffer)?,
+                relation: Relation::from_code(codec::read_uint(buffer, &options)?)?,
+            })
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use content;
+
+    #[test]
+    fn contact_is_encoded_and_decoded()
+    {
+        for &relation in &[Relation::Unfollow, Relation::Follow, Relation::Block] {
+            let contact = Contact { contact: PubKey([3u8; 32]), relation };
+            let (content_type, content) = content::encode(&contact).unwrap();
+            assert_eq!(content_type.as_bytes(), b"contact");
+            assert_eq!(content::decode::<Contact>(b"contact", &content).unwrap(), contact);
+        }
+    }
+
+    #[test]
+    fn unknown_relation_is_rejected()
+    {
+        let mut content = Contact { contact: PubKey([3u8; 32]), relation: Relation::Block }.encode().unwrap();
+        *content.last_mut().unwrap() = 3;
+        assert!(Contact::decode(&content).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+//! Typed message contents
+//!
+//! A payload type implements [`ContentCodec`], which ties it to a content
+//! type identifier. A message with that content type can be decoded with
+//! [`Message::decode_content`], and a [`ContentRegistry`] collects the types
+//! an application understands, so it can check any message against them.
+//!
+//! The standard content types shared by all applications are defined in the
+//! submodules, e.g. text posts in [`post`], feed profiles in [`about`],
+//! follows in [`contact`] and attachments in [`blob`].
+//!
+//! [`ContentCodec`]: trait.ContentCodec.html
+//! [`post`]: post/index.html
+//! [`about`]: about/index.html
+//! [`contact`]: contact/index.html
+//! [`blob`]: blob/index.html
+//! [`ContentRegistry`]: struct.ContentRegistry.html
+//! [`Message::decode_content`]: ../message/struct.Message.html#method.decode_content
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io;
+
+use rmp::encode;
+
+use errors::{Error, ErrorKind, Result, ResultExt};
+use codec;
+use message::{ContentType, Hash, Message, PubKey};
+use options::{DecodeLimits, DecodeOptions};
+
+pub mod about;
+pub mod blob;
+pub mod contact;
+pub mod post;
+
+/// A payload type stored as the content of messages
+pub trait ContentCodec: Sized {
+    /// Identifier of the content type, as stored in the message
+    const CONTENT_TYPE: &'static [u8];
+
+    /// Encodes the value as message content
+    fn encode(&self) -> Result<Vec<u8>>;
+
+    /// Decodes the value from message content
+    fn decode(content: &[u8]) -> Result<Self>;
+
+    /// Checks the rules of the type that the encoding does not enforce
+    ///
+    /// Called after every decode and before encoding by [`encode`].
+    ///
+    /// [`encode`]: fn.encode.html
+    fn validate(&self) -> Result<()>
+    {
+        Ok(())
+    }
+
+    /// The content type of the messages holding this type
+    fn content_type() -> ContentType
+    {
+        ContentType::from_bytes(Self::CONTENT_TYPE.to_vec())
+    }
+}
+
+/// Validates and encodes the value, returns the content type and content of its message
+pub fn encode<T>(value: &T) -> Result<(ContentType, Vec<u8>)>
+    where T: ContentCodec
+{
+    value.validate().map_err(invalid_content::<T>)?;
+    Ok((T::content_type(), value.encode()?))
+}
+
+/// Decodes and validates the content, which must have the content type of `T`
+///
+/// Prefer [`Message::decode_content`], this works on any borrowed message, e.g.
+/// a [`MessageRef`].
+///
+/// [`Message::decode_content`]: ../message/struct.Message.html#method.decode_content
+/// [`MessageRef`]: ../view/struct.MessageRef.html
+pub fn decode<T>(content_type: &[u8], content: &[u8]) -> Result<T>
+    where T: ContentCodec
+{
+    if content_type != T::CONTENT_TYPE {
+        bail!(ErrorKind::UnexpectedContentType(
+            T::content_type().to_string(),
+            ContentType::from_bytes(content_type.to_vec()).to_string()));
+    }
+    let value = T::decode(content).map_err(invalid_content::<T>)?;
+    value.validate().map_err(invalid_content::<T>)?;
+    Ok(value)
+}
+
+/// Wraps the error of decoding or validating `T`, keeping its description
+fn invalid_content<T>(error: Error) -> Error
+    where T: ContentCodec
+{
+    let reason = error.to_string();
+    Error::with_chain(error, ErrorKind::InvalidContent(T::content_type().to_string(), reason))
+}
+
+/// Registers the standard content types of the submodules
+pub fn register_standard_types(registry: &mut ContentRegistry) -> Result<()>
+{
+    registry.register::<post::Post>()?;
+    registry.register::<about::About>()?;
+    registry.register::<contact::Contact>()?;
+    registry.register::<blob::BlobRef>()?;
+    Ok(())
+}
+
+// Helpers of the standard payloads, which are decoded in strict mode
+// so every value has exactly one encoding.
+
+fn read_str<R>(buffer: &mut R) -> Result<String>
+    where R: io::Read
+{
+    let length = codec::read_str_len(buffer, &DecodeOptions::strict())?;
+    let bytes = codec::read_data(buffer, length)?;
+    String::from_utf8(bytes).chain_err(|| "text is not valid UTF-8")
+}
+
+fn read_pubkey<R>(buffer: &mut R) -> Result<PubKey>
+    where R: io::Read
+{
+    let length = codec::read_bin_len(buffer, &DecodeOptions::strict())?;
+    if length != 32 {
+        bail!(ErrorKind::BadPubKeyLength(length));
+    }
+    let mut pubkey = [0u8; 32];
+    buffer.read_exact(&mut pubkey)?;
+    Ok(PubKey(pubkey))
+}
+
+fn read_hash<R>(buffer: &mut R) -> Result<Hash>
+    where R: io::Read
+{
+    let length = codec::read_bin_len(buffer, &DecodeOptions::strict())?;
+    if length != 64 {
+        bail!(ErrorKind::BadHashLength(length));
+    }
+    let mut hash = [0u8; 64];
+    buffer.read_exact(&mut hash)?;
+    Ok(Hash(hash))
+}
+
+/// Reads a msgpack array of items read by `read_item`
+fn read_list<R, T, F>(buffer: &mut R, read_item: F) -> Result<Vec<T>>
+    where R: io::Read, F: Fn(&mut R) -> Result<T>
+{
+    let length = codec::read_array_len(buffer, &DecodeOptions::strict())?;
+    // every item is at least one byte, the content length limits the allocation
+    let mut items = Vec::new();
+    for _ in 0..length {
+        items.push(read_item(buffer)?);
+    }
+    Ok(items)
+}
+
+/// Reads an optional item, encoded as an array of zero or one item like the parent of a message
+fn read_option<R, T, F>(buffer: &mut R, read_item: F) -> Result<Option<T>>
+    where R: io::Read, F: Fn(&mut R) -> Result<T>
+{
+    match codec::read_array_len(buffer, &DecodeOptions::strict())? {
+        0 => Ok(None),
+        1 => Ok(Some(read_item(buffer)?)),
+        length => bail!(ErrorKind::UnexpectedArrayLength(1, length)),
+    }
+}
+
+/// Writes an optional item as read by [`read_option`]
+///
+/// [`read_option`]: fn.read_option.html
+fn write_option<T, F>(buffer: &mut Vec<u8>, value: Option<&T>, write_item: F) -> Result<()>
+    where T: ?Sized, F: Fn(&mut Vec<u8>, &T) -> Result<()>
+{
+    match value {
+        Some(item) => {
+            encode::write_array_len(buffer, 1)?;
+            write_item(buffer, item)
+        },
+        None => {
+            encode::write_array_len(buffer, 0)?;
+            Ok(())
+        },
+    }
+}
+
+/// Decodes a whole payload with `read`, trailing bytes are rejected
+fn decode_payload<T, F>(content: &[u8], read: F) -> Result<T>
+    where F: Fn(&mut &[u8]) -> Result<T>
+{
+    let mut remaining = content;
+    let value = read(&mut remaining)?;
+    codec::check_consumed(remaining, &DecodeOptions::strict())?;
+    Ok(value)
+}
+
+/// The payload types an application understands, by content type
+///
+/// ```ignore
+/// let mut registry = ContentRegistry::new();
+/// registry.register::<Post>()?;
+/// registry.validate(&message)?;
+/// ```
+#[derive(Default)]
+pub struct ContentRegistry {
+    decoders: BTreeMap<Vec<u8>, DecodeFn>,
+}
+
+/// Decodes a registered [`ContentCodec`], erased of its type
+///
+/// [`ContentCodec`]: trait.ContentCodec.html
+type DecodeFn = fn(&[u8], &[u8]) -> Result<Box<dyn Any>>;
+
+fn decode_any<T>(content_type: &[u8], content: &[u8]) -> Result<Box<dyn Any>>
+    where T: ContentCodec + 'static
+{
+    Ok(Box::new(decode::<T>(content_type, content)?))
+}
+
+impl ContentRegistry {
+    pub fn new() -> ContentRegistry
+    {
+        ContentRegistry::default()
+    }
+
+    /// Registers `T` for its content type, which must not be registered yet
+    pub fn register<T>(&mut self) -> Result<()>
+        where T: ContentCodec + 'static
+    {
+        if self.decoders.contains_key(T::CONTENT_TYPE) {
+            bail!(ErrorKind::DuplicateContentType(T::content_type().to_string()));
+        }
+        self.decoders.insert(T::CONTENT_TYPE.to_vec(), decode_any::<T>);
+        Ok(())
+    }
+
+    /// Whether a type is registered for the content type
+    pub fn contains(&self, content_type: &ContentType) -> bool
+    {
+        self.decoders.contains_key(content_type.as_bytes())
+    }
+
+    /// The registered content types, in the order of their bytes
+    pub fn content_types(&self) -> Vec<ContentType>
+    {
+        self.decoders.keys().map(|bytes| ContentType::from_bytes(bytes.clone())).collect()
+    }
+
+    /// Decodes the content with the type registered for its content type
+    ///
+    /// The result can be downcast to the registered type. A message of an
+    /// unregistered content type gives [`ErrorKind::UnknownContentType`].
+    /// A compressed content is decompressed first, within the default [`DecodeLimits`].
+    ///
+    /// [`ErrorKind::UnknownContentType`]: ../errors/enum.ErrorKind.html
+    /// [`DecodeLimits`]: ../options/struct.DecodeLimits.html
+    pub fn decode(&self, message: &Message) -> Result<Box<dyn Any>>
+    {
+        let content_type = message.content_type.as_bytes();
+        match self.decoders.get(content_type) {
+            Some(decode) => decode(content_type, &message.decompressed_content(&DecodeLimits::default())?),
+            None => bail!(ErrorKind::UnknownContentType(message.content_type.to_string())),
+        }
+    }
+
+    /// Checks that the content is a valid value of its registered type
+    pub fn validate(&self, message: &Message) -> Result<()>
+    {
+        self.decode(message).map(|_| ())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compression::Compression;
+    use message::{PubKey, Stamp};
+
+    /// A counter stored as 4 big endian bytes, it must not be zero
+    #[derive(Debug, PartialEq)]
+    struct Counter(u32);
+
+    impl ContentCodec for Counter {
+        const CONTENT_TYPE: &'static [u8] = b"test/counter";
+
+        fn encode(&self) -> Result<Vec<u8>>
+        {
+            let n = self.0;
+            Ok(vec![(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8])
+        }
+
+        fn decode(content: &[u8]) -> Result<Counter>
+        {
+            if content.len() != 4 {
+                bail!("a counter is 4 bytes long");
+            }
+            Ok(Counter(content.iter().fold(0, |n, &byte| n << 8 | u32::from(byte))))
+        }
+
+        fn validate(&self) -> Result<()>
+        {
+            if self.0 == 0 {
+                bail!("a counter can not be zero");
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct Note(String);
+
+    impl ContentCodec for Note {
+        const CONTENT_TYPE: &'static [u8] = b"test/note";
+
+        fn encode(&self) -> Result<Vec<u8>>
+        {
+            Ok(self.0.as_bytes().to_vec())
+        }
+
+        fn decode(content: &[u8]) -> Result<Note>
+        {
+            Ok(Note(String::from_utf8(content.to_vec()).chain_err(|| "a note is UTF-8 text")?))
+        }
+    }
+
+    #[test]
+    fn encoded_content_is_decoded_to_the_same_value()
+    {
+        let message = message_of(&Counter(258));
+        assert_eq!(message.content_type, ContentType::Custom(b"test/counter".to_vec()));
+        assert_eq!(message.decode_content::<Counter>().unwrap(), Counter(258));
+        assert_eq!(message.view().decode_content::<Counter>().unwrap(), Counter(258));
+    }
+
+    #[test]
+    fn other_content_type_is_rejected()
+    {
+        match message_of(&Counter(1)).decode_content::<Note>() {
+            Err(Error(ErrorKind::UnexpectedContentType(ref expected, ref found), _)) => {
+                assert_eq!(expected, "test/note");
+                assert_eq!(found, "test/counter");
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_value_is_neither_encoded_nor_decoded()
+    {
+        assert!(encode(&Counter(0)).is_err());
+
+        let mut message = message_of(&Counter(1));
+        message.content = vec![0, 0, 0, 0];
+        match message.decode_content::<Counter>() {
+            Err(Error(ErrorKind::InvalidContent(ref content_type, _), _)) => assert_eq!(content_type, "test/counter"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        message.content = vec![1];
+        assert!(message.decode_content::<Counter>().is_err());
+    }
+
+    #[test]
+    fn registry_decodes_the_registered_types()
+    {
+        let mut registry = ContentRegistry::new();
+        registry.register::<Counter>().unwrap();
+        registry.register::<Note>().unwrap();
+        assert_eq!(registry.content_types(), [Counter::content_type(), Note::content_type()]);
+
+        let decoded = registry.decode(&message_of(&Counter(7))).unwrap();
+        assert_eq!(decoded.downcast_ref::<Counter>(), Some(&Counter(7)));
+        let decoded = registry.decode(&message_of(&Note("hi".to_string()))).unwrap();
+        assert_eq!(decoded.downcast_ref::<Note>().unwrap().0, "hi");
+    }
+
+    #[test]
+    fn compressed_content_is_decompressed_before_decoding()
+    {
+        let mut message = message_of(&Note("hi hi hi hi hi hi hi hi".to_string()));
+        message.stamp = Some(Stamp { sequence: 0, timestamp: 0 });
+        message.compression = Compression::Deflate;
+        message.content = Compression::Deflate.compress(&message.content).into_owned();
+
+        assert_eq!(message.decode_content::<Note>().unwrap().0, "hi hi hi hi hi hi hi hi");
+        assert_eq!(message.view().decode_content::<Note>().unwrap().0, "hi hi hi hi hi hi hi hi");
+
+        let mut registry = ContentRegistry::new();
+        registry.register::<Note>().unwrap();
+        assert!(registry.validate(&message).is_ok());
+
+        message.content = vec![0xff];
+        assert!(registry.validate(&message).is_err());
+    }
+
+    #[test]
+    fn unknown_content_type_is_reported()
+    {
+        let mut registry = ContentRegistry::new();
+        registry.register::<Counter>().unwrap();
+        let message = message_of(&Note("hi".to_string()));
+
+        assert!(!registry.contains(&message.content_type));
+        match registry.validate(&message) {
+            Err(Error(ErrorKind::UnknownContentType(ref content_type), _)) => assert_eq!(content_type, "test/note"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn content_type_can_be_registered_only_once()
+    {
+        let mut registry = ContentRegistry::new();
+        registry.register::<Counter>().unwrap();
+        assert!(registry.register::<Counter>().is_err());
+    }
+
+    fn message_of<T>(value: &T) -> Message
+        where T: ContentCodec
+    {
+        let (content_type, content) = encode(value).unwrap();
+        Message {
+            author: PubKey([1u8; 32]),
+            parent: None,
+            stamp: None,
+            compression: Compression::None,
+            content_type,
+            content,
+        }
+    }
+}
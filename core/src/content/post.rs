@@ -0,0 +1,161 @@
+//! The `post` content type: a short UTF-8 text
+//!
+//! The content is a msgpack array of four items:
+//!
+//! ```text
+//! [
+//!   text: str,
+//!   reply_to: [] or [bin 64],        the hash of the post it replies to
+//!   mentions: [bin 32, ...],         public keys of the mentioned authors
+//!   links: [bin 64, ...]             hashes of the referenced messages
+//! ]
+//! ```
+//!
+//! Every length and integer uses the shortest msgpack encoding, like in messages.
+
+use rmp::encode;
+
+use errors::Result;
+use codec;
+use message::{Hash, PubKey};
+use options::DecodeOptions;
+use super::{ContentCodec, decode_payload, read_hash, read_list, read_option, read_pubkey, read_str, write_option};
+
+/// A text post, see the [module documentation](index.html)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Post {
+    pub text: String,
+    pub reply_to: Option<Hash>,
+    pub mentions: Vec<PubKey>,
+    pub links: Vec<Hash>,
+}
+
+impl Post {
+    /// A post with only text
+    pub fn new<S>(text: S) -> Post
+        where S: Into<String>
+    {
+        Post {
+            text: text.into(),
+            reply_to: None,
+            mentions: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+}
+
+impl ContentCodec for Post {
+    const CONTENT_TYPE: &'static [u8] = b"post";
+
+    fn encode(&self) -> Result<Vec<u8>>
+    {
+        let mut buffer = Vec::new();
+        encode::write_array_len(&mut buffer, 4)?;
+        encode::write_str(&mut buffer, &self.text)?;
+        write_option(&mut buffer, self.reply_to.as_ref(), |buffer, hash| {
+            encode::write_bin(buffer, &hash.0)?;
+            Ok(())
+        })?;
+        encode::write_array_len(&mut buffer, self.mentions.len() as u32)?;
+        for pubkey in &self.mentions {
+            encode::write_bin(&mut buffer, &pubkey.0)?;
+        }
+        encode::write_array_len(&mut buffer, self.links.len() as u32)?;
+        for hash in &self.links {
+            encode::write_bin(&mut buffer, &hash.0)?;
+        }
+        Ok(buffer)
+    }
+
+    fn decode(content: &[u8]) -> Result<Post>
+    {
+        decode_payload(content, |buffer| {
+            codec::read_array_len_exact(buffer, 4, &DecodeOptions::strict())?;
+            Ok(Post {
+                text: read_str(buffer)?,
+                reply_to: read_option(buffer, read_hash)?,
+                mentions: read_list(buffer, read_pubkey)?,
+                links: read_list(buffer, read_hash)?,
+            })
+        })
+    }
+
+    fn validate(&self) -> Result<()>
+    {
+        if self.text.trim().is_empty() {
+            bail!("the text of a post can not be empty");
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use content;
+
+    #[test]
+    fn post_is_encoded_and_decoded()
+    {
+        let post = Post {
+            text: "hello @bob, see this".to_string(),
+            reply_to: Some(Hash([1u8; 64])),
+            mentions: vec![PubKey([2u8; 32])],
+            links: vec![Hash([3u8; 64]), Hash([4u8; 64])],
+        };
+        let (content_type, content) = content::encode(&post).unwrap();
+        assert_eq!(content_type.as_bytes(), b"post");
+        assert_eq!(content::decode::<Post>(b"post", &content).unwrap(), post);
+    }
+
+    #[test]
+    fn text_only_post_has_a_fixed_encoding()
+    {
+        let content = Post::new("hi").encode().unwrap();
+        assert_eq!(content, [0x94, 0xa2, b'h', b'i', 0x90, 0x90, 0x90]);
+    }
+
+    #[test]
+    fn reply_is_an_optional_hash()
+    {
+        let mut post = Post::new("hi");
+        post.reply_to = Some(Hash([1u8; 64]));
+        let content = post.encode().unwrap();
+        assert_eq!(&content[..6], [0x94, 0xa2, b'h', b'i', 0x91, 0xc4]);
+        assert_eq!(Post::decode(&content).unwrap(), post);
+
+        // two replied posts
+        let mut content = vec![0x94, 0xa2, b'h', b'i', 0x92];
+        for _ in 0..2 {
+            content.extend_from_slice(&[0xc4, 64]);
+            content.extend_from_slice(&[1u8; 64]);
+        }
+        content.extend_from_slice(&[0x90, 0x90]);
+        assert!(Post::decode(&content).is_err());
+    }
+
+    #[test]
+    fn text_must_be_valid_utf8()
+    {
+        let content = [0x94, 0xa2, 0xc3, 0x28, 0x90, 0x90, 0x90];
+        assert!(Post::decode(&content).is_err());
+    }
+
+    #[test]
+    fn empty_text_is_rejected()
+    {
+        assert!(content::encode(&Post::new(" ")).is_err());
+        let content = [0x94, 0xa0, 0x90, 0x90, 0x90];
+        assert!(content::decode::<Post>(b"post", &content).is_err());
+    }
+
+    #[test]
+    fn non_canonical_and_trailing_bytes_are_rejected()
+    {
+        // str8 for a 2 byte text
+        assert!(Post::decode(&[0x94, 0xd9, 0x02, b'h', b'i', 0x90, 0x90, 0x90]).is_err());
+        assert!(Post::decode(&[0x94, 0xa2, b'h', b'i', 0x90, 0x90, 0x90, 0x00]).is_err());
+        assert!(Post::decode(&[0x94, 0xa2, b'h', b'i', 0x90, 0x90]).is_err());
+    }
+}
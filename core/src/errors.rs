@@ -1,40 +1,132 @@
+error_chain!{
+    foreign_links {
+        NumValueReadError(::rmp::decode::NumValueReadError);
+        ValueReadError(::rmp::decode::ValueReadError);
+        ValueWriteError(::rmp::encode::ValueWriteError);
 
-// #![recursion_limit = "1024"]
+        Io(::std::io::Error);
+        Json(::serde_json::Error) #[cfg(feature = "json")];
+    }
 
-use rmp::encode::ValueWriteError;
-use rmp::decode::ValueReadError;
-use rmp::decode::NumValueReadError;
-use std::io::Error;
+    errors {
+        UnexpectedArrayLength(expected: u32, found: u32) {
+            description("unexpected msgpack array length")
+            display("unexpected msgpack array length: expected {}, found {}", expected, found)
+        }
 
-error_chain!{}
+        UnexpectedMarker(marker: u8) {
+            description("unexpected msgpack marker")
+            display("unexpected msgpack marker: 0x{:02x}", marker)
+        }
 
-#[derive(Debug)]
-pub enum ReadError {
-    InvalidMarker,
-    InvalidData,
-    TypeMismatch,
-    Undef,
-}
+        NonCanonicalEncoding(marker: u8) {
+            description("non-canonical msgpack encoding")
+            display("non-canonical msgpack encoding: marker 0x{:02x} is longer than needed", marker)
+        }
 
-/// TODO proper error-structure
-#[derive(Debug)]
-pub enum CodecError {
-    Read(ReadError),
-    Write,
-}
+        TrailingBytes(count: u64) {
+            description("trailing bytes after the encoded value")
+            display("{} trailing bytes after the encoded value", count)
+        }
 
-impl From<ValueWriteError> for CodecError {
-    fn from(_e: ValueWriteError) -> CodecError { CodecError::Write }
-}
+        LimitExceeded(field: String, length: u64, limit: u64) {
+            description("decode limit exceeded")
+            display("{} of {} bytes exceeds the limit of {} bytes", field, length, limit)
+        }
 
-impl From<ValueReadError> for CodecError {
-    fn from(_e: ValueReadError) -> CodecError { CodecError::Read(ReadError::Undef) }
-}
+        UnsupportedFrameVersion(version: u64) {
+            description("unsupported frame version")
+            display("unsupported frame version: {}", version)
+        }
 
-impl From<Error> for CodecError {
-    fn from(_e: Error) -> CodecError { CodecError::Read(ReadError::Undef) }
-}
+        UnknownMessageLayout(items: u32) {
+            description("unknown message layout")
+            display("unknown message layout with {} items", items)
+        }
+
+        BadSignatureLength(length: u32) {
+            description("bad signature length")
+            display("signature length should be 64 bytes, but it is {}", length)
+        }
+
+        BadHashLength(length: u32) {
+            description("bad hash length")
+            display("hash length should be 64 bytes, but it is {}", length)
+        }
+
+        BadPubKeyLength(length: u32) {
+            description("bad public key length")
+            display("public key length should be 32 bytes, but it is {}", length)
+        }
+
+        InvalidPubKey(text: String) {
+            description("invalid public key")
+            display("invalid public key: {:?}", text)
+        }
+
+        InvalidHash(text: String) {
+            description("invalid hash")
+            display("invalid hash: {:?}", text)
+        }
+
+        InvalidMessageId(text: String) {
+            description("invalid message id")
+            display("invalid message id: {:?}", text)
+        }
+
+        InvalidJsonFrame(reason: String) {
+            description("invalid JSON frame")
+            display("invalid JSON frame: {}", reason)
+        }
+
+        UnknownContentType(content_type: String) {
+            description("unknown content type")
+            display("unknown content type {:?}", content_type)
+        }
+
+        UnexpectedContentType(expected: String, found: String) {
+            description("unexpected content type")
+            display("unexpected content type: expected {:?}, found {:?}", expected, found)
+        }
+
+        InvalidContent(content_type: String, reason: String) {
+            description("invalid content")
+            display("invalid {:?} content: {}", content_type, reason)
+        }
+
+        DuplicateContentType(content_type: String) {
+            description("content type is already registered")
+            display("content type {:?} is already registered", content_type)
+        }
+
+        UnknownCompression(code: u64) {
+            description("unknown compression method")
+            display("unknown compression method: {}", code)
+        }
+
+        DecompressionLimitExceeded(limit: u64) {
+            description("decompressed content exceeds the limit")
+            display("decompressed content exceeds the limit of {} bytes", limit)
+        }
+
+        CorruptCompressedContent(reason: String) {
+            description("corrupt compressed content")
+            display("corrupt compressed content: {}", reason)
+        }
+
+        TruncatedFrame(offset: u64) {
+            description("truncated frame")
+            display("truncated frame at byte offset {}", offset)
+        }
+
+        CorruptFrame(offset: u64) {
+            description("corrupt frame")
+            display("corrupt frame at byte offset {}", offset)
+        }
 
-impl From<NumValueReadError> for CodecError {
-    fn from(_e: NumValueReadError) -> CodecError { CodecError::Read(ReadError::Undef) }
+        BrokenChain(position: u64, violation: ::validator::ChainViolation) {
+            description("broken feed chain")
+            display("broken feed chain at message #{}: {}", position, violation)
+        }
+    }
 }
@@ -1,22 +1,33 @@
 
 use std::io;
-use super::errors::Result;
+use super::errors::{ErrorKind, Result};
+use codec;
 use signature::Signature;
-use message::{Message, PubKey};
+use message::{Message, MessageId, PubKey};
+use options::DecodeOptions;
+use view::FrameRef;
 use ring;
 
 /// The `Frame` wraps the [`Message`] and provides its signature.
 ///
-/// Changing the version field means changing the format of the `Frame`
+/// Changing the version field means changing the format of the `Frame`.
+/// The version is also the layout version of the wrapped message:
+/// version 1 messages have no [`Stamp`], version 2 messages have one,
+/// version 3 messages also name the [`Compression`] of their content.
 ///
-/// [`Message`]: struct.Message.html
+/// [`Message`]: ../message/struct.Message.html
+/// [`Stamp`]: ../message/struct.Stamp.html
+/// [`Compression`]: ../compression/enum.Compression.html
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Frame {
-    /// a special value that is always 1 for this given `Frame`.
+    /// the layout version of the `Frame` and of the wrapped message, 1, 2 or 3
     pub version: u32,
 
     /// the serialized format of the [`Message`].
     /// [`Message`]: struct.Message.html
+    #[cfg_attr(feature = "serde", serde(with = "::serde_impls::base64_bytes"))]
     pub message: Vec<u8>,
 
     /// The Ed25519 signature of the `message` field.
@@ -26,71 +37,136 @@ pub struct Frame {
 impl Frame {
     pub fn new_signed(message: &Message, keypair: &ring::signature::Ed25519KeyPair) -> Result<Frame>
     {
-        let mut buffer: Vec<u8> = Vec::new();
-        message.write(&mut buffer)?;
-        let signature = keypair.sign(Frame::digest(&buffer).as_ref());
+        // the digest is computed while serializing, without a second pass
+        let mut writer = codec::DigestWriter::new(Vec::with_capacity(message.encoded_len()));
+        message.write(&mut writer)?;
+        let (buffer, digest) = writer.finish();
+        let signature = keypair.sign(digest.as_ref());
         Ok(Frame {
-            version: 1,
+            version: message.version(),
             message: buffer,
             signature: Signature::new(signature.as_ref())?,
         })
     }
 
     /// Reads a [`Frame`] from a buffer, but does not verifies the signature
+    ///
+    /// Every supported version is accepted, the wrapped message is not decoded.
+    /// Malformed input results an error, it never panics.
     pub fn read<R>(buffer: &mut R) -> Result<Frame>
         where R: io::Read
     {
-        use rmp::decode;
+        Frame::read_with(buffer, &DecodeOptions::default())
+    }
 
-        let array_len = decode::read_array_len(buffer)?;
-        assert_eq!(array_len, 3);
-        let version = decode::read_int::<u32, R>(buffer)?;
-        assert_eq!(version, 1);
+    /// Same as [`read`], with the given [`DecodeOptions`]
+    ///
+    /// [`read`]: #method.read
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn read_with<R>(buffer: &mut R, options: &DecodeOptions) -> Result<Frame>
+        where R: io::Read
+    {
+        codec::read_array_len_exact(buffer, 3, options)?;
+        let version = codec::read_uint(buffer, options)?;
+        if !(1..=3).contains(&version) {
+            bail!(ErrorKind::UnsupportedFrameVersion(version));
+        }
 
-        let message_len = decode::read_bin_len(buffer)?;
-        let mut message_buffer = vec![0u8; message_len as usize];
-        buffer.read_exact(&mut message_buffer[..])?;
+        let message_buffer = codec::read_bin(buffer, options, codec::Field::Message)?;
 
-        let signature_len = decode::read_bin_len(buffer)?;
-        assert_eq!(signature_len, 64);
+        let signature_len = codec::read_bin_len(buffer, options)?;
+        if signature_len != 64 {
+            bail!(ErrorKind::BadSignatureLength(signature_len));
+        }
         let mut signature_buffer = [0u8; 64];
         buffer.read_exact(&mut signature_buffer[..])?;
 
         Ok(Frame {
-            version: 1,
+            version: version as u32,
             message: message_buffer,
             signature: Signature(signature_buffer),
         })
     }
 
+    /// Decodes a [`Frame`] from a complete buffer
+    ///
+    /// In strict mode trailing bytes after the frame are rejected.
+    pub fn decode(bytes: &[u8], options: &DecodeOptions) -> Result<Frame>
+    {
+        let mut remaining = bytes;
+        let frame = Frame::read_with(&mut remaining, options)?;
+        codec::check_consumed(remaining, options)?;
+        Ok(frame)
+    }
+
+    /// Decodes the wrapped message, which must have the layout of the frame's version
+    pub fn decode_message(&self) -> Result<Message>
+    {
+        self.decode_message_with(&DecodeOptions::default())
+    }
+
+    /// Same as [`decode_message`], with the given [`DecodeOptions`]
+    ///
+    /// In strict mode the message must be canonical and fill the whole
+    /// `message` field.
+    ///
+    /// [`decode_message`]: #method.decode_message
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn decode_message_with(&self, options: &DecodeOptions) -> Result<Message>
+    {
+        let mut remaining = &self.message[..];
+        let message = Message::read_version(&mut remaining, self.version, options)?;
+        codec::check_consumed(remaining, options)?;
+        Ok(message)
+    }
 
     pub fn verify(&self, pubkey: &PubKey) -> bool
     {
-        use ::untrusted::Input;
-        let digest = Frame::digest(&self.message);
-        let message = Input::from(digest.as_ref());
+        self.view().verify(pubkey)
+    }
+
+    /// A borrowed [`FrameRef`] of this frame
+    ///
+    /// [`FrameRef`]: ../view/struct.FrameRef.html
+    pub fn view(&self) -> FrameRef<'_>
+    {
+        FrameRef {
+            version: self.version,
+            message: &self.message,
+            signature: self.signature,
+        }
+    }
 
-        let signature = Input::from(&self.signature.0[..]);
-        let pubkey_bytes = Input::from(&pubkey.0[..]);
-        ring::signature::verify(&ring::signature::ED25519,
-                                pubkey_bytes,
-                                message,
-                                signature).is_ok()
+    /// The identifier of the wrapped message, its [`Hash`] is the `parent` of the next message
+    ///
+    /// [`Hash`]: ../message/struct.Hash.html
+    pub fn message_id(&self) -> MessageId
+    {
+        MessageId::of_bytes(&self.message)
     }
 
-    pub fn write(&self, buffer: &mut Vec<u8>) -> Result<u32>
+    /// Encodes the `Frame` in the msgpack format and returns the number of written bytes
+    pub fn write<W>(&self, buffer: &mut W) -> Result<usize>
+        where W: io::Write + ?Sized
     {
         use rmp::encode;
-        encode::write_array_len(buffer, 3)?;
-        encode::write_uint(buffer, 1)?; // version
-        encode::write_bin(buffer, self.message.as_ref())?;
-        encode::write_bin(buffer, &self.signature.0[..])?;
-        Ok(0u32)
+        let mut writer = codec::CountingWriter::new(buffer);
+        encode::write_array_len(&mut writer, 3)?;
+        encode::write_uint(&mut writer, u64::from(self.version))?;
+        encode::write_bin(&mut writer, self.message.as_ref())?;
+        encode::write_bin(&mut writer, &self.signature.0[..])?;
+        Ok(writer.count())
     }
 
-    fn digest(buffer: &Vec<u8>) -> ring::digest::Digest
+    /// Number of bytes written by [`write`]
+    ///
+    /// [`write`]: #method.write
+    pub fn encoded_len(&self) -> usize
     {
-        ring::digest::digest(&ring::digest::SHA512, &buffer[..])
+        codec::array_header_len(3)
+            + codec::uint_len(u64::from(self.version))
+            + codec::bin_len(self.message.len())
+            + codec::bin_len(64)
     }
 }
 
@@ -98,6 +174,8 @@ impl Frame {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use compression::Compression;
+    use message::Stamp;
 
     #[test]
     fn signature_comparison()
@@ -109,6 +187,114 @@ mod tests {
 
     mod encoding_and_decoding {
         use super::*;
+        use errors::Error;
+
+        #[test]
+        fn written_length_is_returned_and_predicted()
+        {
+            let frame = create_test_frame();
+            let mut buffer = Vec::new();
+            let written = frame.write(&mut buffer).unwrap();
+
+            assert_eq!(written, buffer.len());
+            assert_eq!(frame.encoded_len(), buffer.len());
+        }
+
+        #[test]
+        fn unsupported_version_is_rejected()
+        {
+            let mut buffer = Vec::new();
+            test_frame().write(&mut buffer).unwrap();
+            buffer[1] = 7;
+
+            match Frame::read(&mut io::Cursor::new(buffer)) {
+                Err(Error(ErrorKind::UnsupportedFrameVersion(7), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn version_is_written_and_read_back()
+        {
+            let mut frame = test_frame();
+            frame.version = 2;
+            assert_eq!(encode_decode(&frame).version, 2);
+        }
+
+        #[test]
+        fn bad_signature_length_is_rejected()
+        {
+            // [1, bin8(1), bin8(3)]
+            let buffer = vec![0x93, 0x01, 0xc4, 0x01, 0x42, 0xc4, 0x03, 0x01, 0x02, 0x03];
+
+            match Frame::read(&mut io::Cursor::new(buffer)) {
+                Err(Error(ErrorKind::BadSignatureLength(3), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn non_minimal_version_is_rejected_only_in_strict_mode()
+        {
+            let mut buffer = Vec::new();
+            test_frame().write(&mut buffer).unwrap();
+            // version 1 as uint8
+            buffer.splice(1..2, vec![0xcc, 0x01]);
+
+            match Frame::decode(&buffer, &DecodeOptions::strict()) {
+                Err(Error(ErrorKind::NonCanonicalEncoding(0xcc), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            assert_eq!(Frame::decode(&buffer, &DecodeOptions::default()).unwrap().version, 1);
+        }
+
+        #[test]
+        fn trailing_bytes_are_rejected_only_in_strict_mode()
+        {
+            let mut buffer = Vec::new();
+            test_frame().write(&mut buffer).unwrap();
+            buffer.push(0x00);
+
+            assert!(Frame::decode(&buffer, &DecodeOptions::strict()).is_err());
+            assert!(Frame::decode(&buffer, &DecodeOptions::default()).is_ok());
+        }
+
+        #[test]
+        fn message_above_the_size_limit_is_rejected()
+        {
+            let mut buffer = Vec::new();
+            test_frame().write(&mut buffer).unwrap();
+
+            let mut options = DecodeOptions::default();
+            options.limits.max_message_size = 3;
+            assert!(Frame::decode(&buffer, &options).is_ok());
+
+            options.limits.max_message_size = 2;
+            match Frame::decode(&buffer, &options) {
+                Err(Error(ErrorKind::LimitExceeded(ref field, 3, 2), _)) if field == "message" => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn wrong_array_length_is_rejected()
+        {
+            let buffer = vec![0x92, 0x01, 0xc4, 0x00];
+
+            match Frame::read(&mut io::Cursor::new(buffer)) {
+                Err(Error(ErrorKind::UnexpectedArrayLength(3, 2), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        fn test_frame() -> Frame
+        {
+            Frame {
+                version: 1,
+                message: vec![0x42, 0x2a, 0x66],
+                signature: Signature([42u8; 64]),
+            }
+        }
 
         #[test]
         fn simple_back_and_forth()
@@ -135,7 +321,7 @@ mod tests {
     {
         use ::message::PubKey;
         let frame = create_test_frame();
-        let pubkey = PubKey::new(TEST_PUBKEY);
+        let pubkey = PubKey::from_slice(TEST_PUBKEY).unwrap();
         assert!(frame.verify(&pubkey))
     }
 
@@ -145,7 +331,7 @@ mod tests {
         let frame = create_test_frame();
         let decoded_frame = encode_decode(&frame);
 
-        let pubkey = PubKey::new(TEST_PUBKEY);
+        let pubkey = PubKey::from_slice(TEST_PUBKEY).unwrap();
         assert!(decoded_frame.verify(&pubkey))
     }
 
@@ -154,18 +340,99 @@ mod tests {
     {
         use ::message::{PubKey, ContentType, Message};
         let message = Message {
-            author: PubKey::new(TEST_PUBKEY),
+            author: PubKey::from_slice(TEST_PUBKEY).unwrap(),
             parent: None,
+            stamp: None,
+            compression: Compression::None,
             content_type: ContentType::Blob,
             content: vec![42u8, 44u8],
         };
         let privkey = load_key(TEST_PRIVKEY).expect("could not load privkey");
         let frame = Frame::new_signed(&message, &privkey).expect("could not create Frame");
 
-        let wrong_pubkey = PubKey::new(WRONG_PUBKEY);
+        let wrong_pubkey = PubKey::from_slice(WRONG_PUBKEY).unwrap();
         assert!(!frame.verify(&wrong_pubkey));
     }
 
+    #[test]
+    fn stamped_message_is_wrapped_in_a_version_2_frame()
+    {
+        let mut message = create_test_message();
+        message.stamp = Some(Stamp { sequence: 3, timestamp: 1_500_000_000_000 });
+        let privkey = load_key(TEST_PRIVKEY).expect("could not load privkey");
+        let frame = encode_decode(&Frame::new_signed(&message, &privkey).unwrap());
+
+        assert_eq!(frame.version, 2);
+        assert!(frame.verify(&PubKey::from_slice(TEST_PUBKEY).unwrap()));
+        assert_eq!(frame.decode_message().unwrap().stamp, message.stamp);
+    }
+
+    #[test]
+    fn compressed_message_is_wrapped_in_a_version_3_frame()
+    {
+        use options::DecodeLimits;
+        let mut message = create_test_message();
+        message.stamp = Some(Stamp { sequence: 3, timestamp: 1_500_000_000_000 });
+        message.compression = Compression::Deflate;
+        message.content = Compression::Deflate.compress(&[42u8; 64]).into_owned();
+        let privkey = load_key(TEST_PRIVKEY).expect("could not load privkey");
+        let frame = encode_decode(&Frame::new_signed(&message, &privkey).unwrap());
+
+        assert_eq!(frame.version, 3);
+        assert!(frame.verify(&PubKey::from_slice(TEST_PUBKEY).unwrap()));
+        let decoded = frame.decode_message().unwrap();
+        assert_eq!(decoded.decompressed_content(&DecodeLimits::default()).unwrap().as_ref(), &[42u8; 64][..]);
+    }
+
+    #[test]
+    fn message_layout_must_match_the_frame_version()
+    {
+        let mut frame = create_test_frame();
+        assert!(frame.decode_message().unwrap().stamp.is_none());
+
+        frame.version = 2;
+        assert!(frame.decode_message().is_err());
+    }
+
+    #[test]
+    fn signed_frame_and_its_message_are_canonical()
+    {
+        let mut buffer = Vec::new();
+        create_test_frame().write(&mut buffer).unwrap();
+
+        let frame = Frame::decode(&buffer, &DecodeOptions::strict()).unwrap();
+        assert!(frame.decode_message_with(&DecodeOptions::strict()).is_ok());
+    }
+
+    #[test]
+    fn message_with_trailing_bytes_is_rejected_in_strict_mode()
+    {
+        let mut frame = create_test_frame();
+        frame.message.push(0x00);
+
+        assert!(frame.decode_message_with(&DecodeOptions::strict()).is_err());
+        assert!(frame.decode_message().is_ok());
+    }
+
+    #[test]
+    fn content_above_the_limit_is_rejected()
+    {
+        let frame = create_test_frame();
+        let mut options = DecodeOptions::default();
+        options.limits.max_content_len = 1;
+
+        assert!(frame.decode_message_with(&options).is_err());
+        assert!(frame.view().decode_message(&options).is_err());
+        assert!(frame.decode_message().is_ok());
+    }
+
+    #[test]
+    fn message_id_is_the_id_of_the_wrapped_message()
+    {
+        let frame = create_test_frame();
+        assert_eq!(frame.message_id(), create_test_message().id().unwrap());
+    }
+
     fn create_test_frame() -> Frame
     {
         let message = create_test_message();
@@ -177,8 +444,10 @@ mod tests {
     {
         use ::message::{PubKey, ContentType, Message};
         Message {
-            author: PubKey::new(TEST_PUBKEY),
+            author: PubKey::from_slice(TEST_PUBKEY).unwrap(),
             parent: None,
+            stamp: None,
+            compression: Compression::None,
             content_type: ContentType::Blob,
             content: vec![42u8, 44u8],
         }
@@ -0,0 +1,200 @@
+//! The follow graph of authors, folded from their `contact` messages
+//!
+//! See [`content::contact`] about the messages.
+//!
+//! [`content::contact`]: ../content/contact/index.html
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use content::ContentCodec;
+use content::contact::{Contact, Relation};
+use message::{Message, PubKey};
+
+/// The latest relations between authors
+///
+/// Messages must be applied in feed order, a later relation of an author
+/// to the same contact replaces the earlier one.
+#[derive(Debug, Clone, Default)]
+pub struct SocialGraph {
+    relations: BTreeMap<PubKey, BTreeMap<PubKey, Relation>>,
+}
+
+impl SocialGraph {
+    pub fn new() -> SocialGraph
+    {
+        SocialGraph::default()
+    }
+
+    /// Records the relation of `author` to the contact
+    pub fn apply(&mut self, author: PubKey, contact: &Contact)
+    {
+        let relations = self.relations.entry(author).or_default();
+        match contact.relation {
+            Relation::Unfollow => { relations.remove(&contact.contact); },
+            relation => { relations.insert(contact.contact, relation); },
+        }
+    }
+
+    /// Applies the message if it is a valid `contact` message, returns whether it was one
+    pub fn apply_message(&mut self, message: &Message) -> bool
+    {
+        if message.content_type.as_bytes() != Contact::CONTENT_TYPE {
+            return false;
+        }
+        match message.decode_content::<Contact>() {
+            Ok(contact) => {
+                self.apply(message.author, &contact);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// The current relation of `author` to `contact`, `None` if there is none
+    pub fn relation(&self, author: &PubKey, contact: &PubKey) -> Option<Relation>
+    {
+        self.relations.get(author).and_then(|relations| relations.get(contact)).cloned()
+    }
+
+    /// The authors followed by `author`
+    pub fn follows(&self, author: &PubKey) -> Vec<PubKey>
+    {
+        self.with_relation(author, Relation::Follow)
+    }
+
+    /// The authors blocked by `author`
+    pub fn blocks(&self, author: &PubKey) -> Vec<PubKey>
+    {
+        self.with_relation(author, Relation::Block)
+    }
+
+    /// The authors following `contact`
+    pub fn followers(&self, contact: &PubKey) -> Vec<PubKey>
+    {
+        self.relations.iter()
+            .filter(|&(_, relations)| relations.get(contact) == Some(&Relation::Follow))
+            .map(|(author, _)| *author)
+            .collect()
+    }
+
+    /// Hop distance of the authors reachable from `roots` by following at most `max_hops` follows
+    ///
+    /// The roots, usually our own keys, are at distance zero. Authors blocked by
+    /// any of the roots are never reached, even if others follow them.
+    pub fn hops(&self, roots: &[PubKey], max_hops: u32) -> BTreeMap<PubKey, u32>
+    {
+        let blocked: BTreeSet<PubKey> = roots.iter().flat_map(|root| self.blocks(root)).collect();
+        let mut distances = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        for root in roots {
+            distances.insert(*root, 0);
+            queue.push_back(*root);
+        }
+        while let Some(author) = queue.pop_front() {
+            let distance = distances[&author];
+            if distance == max_hops {
+                continue;
+            }
+            for contact in self.follows(&author) {
+                if !blocked.contains(&contact) && !distances.contains_key(&contact) {
+                    distances.insert(contact, distance + 1);
+                    queue.push_back(contact);
+                }
+            }
+        }
+        distances
+    }
+
+    fn with_relation(&self, author: &PubKey, relation: Relation) -> Vec<PubKey>
+    {
+        match self.relations.get(author) {
+            Some(relations) => relations.iter()
+                .filter(|&(_, r)| *r == relation)
+                .map(|(contact, _)| *contact)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compression::Compression;
+    use content;
+
+    fn key(n: u8) -> PubKey
+    {
+        PubKey([n; 32])
+    }
+
+    fn graph(edges: &[(u8, u8, Relation)]) -> SocialGraph
+    {
+        let mut graph = SocialGraph::new();
+        for &(author, contact, relation) in edges {
+            graph.apply(key(author), &Contact { contact: key(contact), relation });
+        }
+        graph
+    }
+
+    #[test]
+    fn later_relation_replaces_the_earlier_one()
+    {
+        let graph = graph(&[(1, 2, Relation::Follow), (1, 3, Relation::Follow), (1, 2, Relation::Unfollow)]);
+        assert_eq!(graph.relation(&key(1), &key(2)), None);
+        assert_eq!(graph.follows(&key(1)), [key(3)]);
+        assert_eq!(graph.followers(&key(3)), [key(1)]);
+    }
+
+    #[test]
+    fn hops_are_counted_along_follows()
+    {
+        let graph = graph(&[
+            (1, 2, Relation::Follow),
+            (2, 3, Relation::Follow),
+            (3, 4, Relation::Follow),
+            (1, 3, Relation::Follow),
+        ]);
+        let hops = graph.hops(&[key(1)], 2);
+        let expected: BTreeMap<PubKey, u32> = [(key(1), 0), (key(2), 1), (key(3), 1), (key(4), 2)].iter().cloned().collect();
+        assert_eq!(hops, expected);
+
+        assert_eq!(graph.hops(&[key(1)], 0).len(), 1);
+    }
+
+    #[test]
+    fn authors_blocked_by_the_root_are_not_reached()
+    {
+        let graph = graph(&[
+            (1, 2, Relation::Follow),
+            (2, 3, Relation::Follow),
+            (3, 4, Relation::Follow),
+            (1, 3, Relation::Block),
+        ]);
+        let hops = graph.hops(&[key(1)], 3);
+        assert!(!hops.contains_key(&key(3)));
+        assert!(!hops.contains_key(&key(4)));
+        assert_eq!(hops.get(&key(2)), Some(&1));
+    }
+
+    #[test]
+    fn only_valid_contact_messages_are_applied()
+    {
+        let (content_type, content) = content::encode(&Contact { contact: key(2), relation: Relation::Follow }).unwrap();
+        let mut message = Message {
+            author: key(1),
+            parent: None,
+            stamp: None,
+            compression: Compression::None,
+            content_type,
+            content,
+        };
+        let mut graph = SocialGraph::new();
+        assert!(graph.apply_message(&message));
+        assert_eq!(graph.follows(&key(1)), [key(2)]);
+
+        message.content.push(0);
+        assert!(!graph.apply_message(&message));
+    }
+}
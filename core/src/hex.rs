@@ -0,0 +1,75 @@
+//! Lowercase hexadecimal encoding of byte arrays
+
+use std::fmt;
+
+/// Writes the bytes as lowercase hex digits
+pub fn write(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result
+{
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+/// Decodes exactly `output.len()` bytes from hex digits (any case)
+///
+/// Returns `None` if the text has a different length or contains other characters.
+pub fn decode_into(text: &str, output: &mut [u8]) -> Option<()>
+{
+    let digits = text.as_bytes();
+    if digits.len() != output.len() * 2 {
+        return None;
+    }
+    for (byte, pair) in output.iter_mut().zip(digits.chunks(2)) {
+        *byte = (digit_value(pair[0])? << 4) | digit_value(pair[1])?;
+    }
+    Some(())
+}
+
+fn digit_value(digit: u8) -> Option<u8>
+{
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Hex<'a>(&'a [u8]);
+
+    impl<'a> fmt::Display for Hex<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write(f, self.0)
+        }
+    }
+
+    #[test]
+    fn bytes_are_encoded_with_two_digits_each()
+    {
+        assert_eq!(Hex(&[0x00, 0x0f, 0xa0, 0xff]).to_string(), "000fa0ff");
+    }
+
+    #[test]
+    fn encoded_bytes_can_be_decoded()
+    {
+        let mut output = [0u8; 4];
+        assert_eq!(decode_into("000fA0ff", &mut output), Some(()));
+        assert_eq!(output, [0x00, 0x0f, 0xa0, 0xff]);
+    }
+
+    #[test]
+    fn invalid_text_is_rejected()
+    {
+        let mut output = [0u8; 2];
+        assert_eq!(decode_into("abc", &mut output), None);
+        assert_eq!(decode_into("abcdef", &mut output), None);
+        assert_eq!(decode_into("abcg", &mut output), None);
+        assert_eq!(decode_into("ab+d", &mut output), None);
+    }
+}
@@ -0,0 +1,292 @@
+//! JSON interchange format of frames, enabled by the `json` feature
+//!
+//! A frame is represented as a JSON object with its decoded message:
+//!
+//! ```text
+//! {
+//!   "version": 2,
+//!   "id": "<hex message id>",
+//!   "message": {
+//!     "author": "@<base64 public key>.ed25519",
+//!     "parent": "<hex hash>" or null,
+//!     "stamp": {"sequence": 1, "timestamp": 1500000000000} or null,
+//!     "compression": "none" or "deflate",
+//!     "content_type": "<base64>",
+//!     "content": "<base64>"
+//!   },
+//!   "signature": "<base64>"
+//! }
+//! ```
+//!
+//! The `id` is informative for the reader, on import it must match the message.
+//! The message is re-encoded on import, so only frames with a canonically encoded
+//! message can be exported: this way the round trip gives back the exact signed bytes.
+
+use serde_json;
+
+use errors::{ErrorKind, Result, ResultExt};
+use codec;
+use frame::Frame;
+use message::{Message, MessageId};
+use options::{DecodeLimits, DecodeOptions};
+use signature::Signature;
+
+/// A [`Frame`] with its decoded message, see the [module documentation](index.html)
+///
+/// [`Frame`]: ../frame/struct.Frame.html
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonFrame {
+    pub version: u32,
+    pub id: MessageId,
+    pub message: Message,
+    pub signature: Signature,
+}
+
+impl JsonFrame {
+    /// Decodes the message of the `frame`, it must be canonically encoded
+    pub fn from_frame(frame: &Frame) -> Result<JsonFrame>
+    {
+        let message = frame.decode_message_with(&DecodeOptions::strict())
+            .chain_err(|| ErrorKind::InvalidJsonFrame("the message is not canonically encoded".to_string()))?;
+        Ok(JsonFrame {
+            version: frame.version,
+            id: frame.message_id(),
+            message,
+            signature: frame.signature,
+        })
+    }
+
+    /// Encodes the message back into a [`Frame`], the signature is not verified
+    ///
+    /// [`Frame`]: ../frame/struct.Frame.html
+    pub fn to_frame(&self) -> Result<Frame>
+    {
+        if self.version != self.message.version() {
+            bail!(ErrorKind::InvalidJsonFrame(format!(
+                "version {} does not match the layout of the message", self.version)));
+        }
+        let mut buffer = Vec::with_capacity(self.message.encoded_len());
+        self.message.write(&mut buffer)?;
+        let frame = Frame {
+            version: self.version,
+            message: buffer,
+            signature: self.signature,
+        };
+        if frame.message_id() != self.id {
+            bail!(ErrorKind::InvalidJsonFrame(format!(
+                "id {} does not match the message, its id is {}", self.id, frame.message_id())));
+        }
+        Ok(frame)
+    }
+}
+
+/// Room for the JSON form of the fields besides the content and the content type
+const JSON_FRAME_OVERHEAD: u64 = 4096;
+
+/// Upper bound of the length of a JSON frame whose message is within `limits`
+///
+/// The content and the content type are base64 encoded, 4 characters for every
+/// 3 bytes, and they are shorter than the message holding them.
+pub fn max_text_len(limits: &DecodeLimits) -> u64
+{
+    u64::from(limits.max_message_size).div_ceil(3) * 4 + JSON_FRAME_OVERHEAD
+}
+
+/// Encodes the `frame` as a single line JSON object
+pub fn to_string(frame: &Frame) -> Result<String>
+{
+    Ok(serde_json::to_string(&JsonFrame::from_frame(frame)?)?)
+}
+
+/// Decodes a frame encoded by [`to_string`]
+///
+/// The message must be within the default [`DecodeLimits`].
+///
+/// [`DecodeLimits`]: ../options/struct.DecodeLimits.html
+/// [`to_string`]: fn.to_string.html
+pub fn from_str(text: &str) -> Result<Frame>
+{
+    from_str_with(text, &DecodeOptions::strict())
+}
+
+/// Same as [`from_str`], the message must be within the limits of `options`
+///
+/// A `text` longer than [`max_text_len`] is rejected before it is parsed.
+///
+/// [`from_str`]: fn.from_str.html
+/// [`max_text_len`]: fn.max_text_len.html
+pub fn from_str_with(text: &str, options: &DecodeOptions) -> Result<Frame>
+{
+    let limit = max_text_len(&options.limits);
+    if text.len() as u64 > limit {
+        bail!(ErrorKind::LimitExceeded("JSON frame".to_string(), text.len() as u64, limit));
+    }
+    let json_frame: JsonFrame = serde_json::from_str(text)?;
+    let frame = json_frame.to_frame()?;
+    // the same limits apply as to binary frames
+    codec::check_field_len(frame.message.len() as u64, options, codec::Field::Message)?;
+    frame.view().decode_message(options)?;
+    Ok(frame)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::Error;
+    use compression::Compression;
+    use message::{ContentType, Hash, PubKey, Stamp};
+    use ::load_key;
+    use ::generate_private_key;
+
+    #[test]
+    fn signed_frame_round_trips_to_the_same_bytes()
+    {
+        let keypair = load_key(generate_private_key().unwrap().as_bytes()).unwrap();
+        let author = PubKey::from_keypair(&keypair);
+        let stamp = Stamp { sequence: 1, timestamp: 1_500_000_000_000 };
+        let layouts = [
+            (None, Compression::None),
+            (Some(stamp), Compression::None),
+            (Some(stamp), Compression::Deflate),
+        ];
+        for &(stamp, compression) in &layouts {
+            let message = Message {
+                author,
+                parent: Some(Hash([2u8; 64])),
+                stamp,
+                compression,
+                content_type: ContentType::Custom(b"post".to_vec()),
+                content: b"hello".to_vec(),
+            };
+            let frame = Frame::new_signed(&message, &keypair).unwrap();
+
+            let text = to_string(&frame).unwrap();
+            assert!(!text.contains('\n'));
+            let decoded = from_str(&text).unwrap();
+
+            assert_eq!(decoded.version, frame.version);
+            assert_eq!(decoded.message, frame.message);
+            assert_eq!(decoded.signature, frame.signature);
+            assert!(decoded.verify(&author));
+        }
+    }
+
+    #[test]
+    fn json_contains_the_decoded_message_and_the_id()
+    {
+        let frame = test_frame();
+        let json: serde_json::Value = serde_json::from_str(&to_string(&frame).unwrap()).unwrap();
+
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["id"], frame.message_id().to_string());
+        assert_eq!(json["message"]["author"], PubKey([1u8; 32]).to_string());
+        assert_eq!(json["message"]["parent"], serde_json::Value::Null);
+        assert_eq!(json["message"]["compression"], "none");
+        assert_eq!(json["message"]["content"], "AQI=");
+    }
+
+    #[test]
+    fn mismatching_id_is_rejected()
+    {
+        let mut json: serde_json::Value = serde_json::from_str(&to_string(&test_frame()).unwrap()).unwrap();
+        json["id"] = serde_json::Value::String(MessageId([0u8; 64]).to_string());
+
+        match from_str(&json.to_string()) {
+            Err(Error(ErrorKind::InvalidJsonFrame(_), _)) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mismatching_version_is_rejected()
+    {
+        let mut json: serde_json::Value = serde_json::from_str(&to_string(&test_frame()).unwrap()).unwrap();
+        json["version"] = serde_json::Value::from(2);
+
+        match from_str(&json.to_string()) {
+            Err(Error(ErrorKind::InvalidJsonFrame(_), _)) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn message_above_the_size_limit_is_rejected()
+    {
+        let text = to_string(&test_frame()).unwrap();
+        let mut options = DecodeOptions::strict();
+        options.limits.max_message_size = 10;
+
+        match from_str_with(&text, &options) {
+            Err(Error(ErrorKind::LimitExceeded(ref field, _, 10), _)) => assert_eq!(field, "message"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(from_str(&text).is_ok());
+    }
+
+    #[test]
+    fn text_above_the_size_limit_is_rejected_before_parsing()
+    {
+        let mut options = DecodeOptions::strict();
+        options.limits.max_message_size = 10;
+        let limit = max_text_len(&options.limits);
+        // not even JSON, parsing it would fail with another error
+        let text = "x".repeat(limit as usize + 1);
+
+        match from_str_with(&text, &options) {
+            Err(Error(ErrorKind::LimitExceeded(ref field, length, max), _)) => {
+                assert_eq!(field, "JSON frame");
+                assert_eq!((length, max), (limit + 1, limit));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_of_the_largest_message_is_within_the_limit()
+    {
+        let message = Message {
+            author: PubKey([1u8; 32]),
+            parent: Some(Hash([2u8; 64])),
+            stamp: Some(Stamp { sequence: !0, timestamp: !0 }),
+            compression: Compression::Deflate,
+            content_type: ContentType::Custom(vec![b'x'; DecodeLimits::default().max_content_type_len as usize]),
+            content: vec![0xffu8; DecodeLimits::default().max_content_len as usize],
+        };
+        let mut buffer = Vec::new();
+        message.write(&mut buffer).unwrap();
+        let frame = Frame { version: 3, message: buffer, signature: Signature([3u8; 64]) };
+
+        let text = to_string(&frame).unwrap();
+        assert!(text.len() as u64 <= max_text_len(&DecodeLimits::default()));
+        assert!(from_str(&text).is_ok());
+    }
+
+    #[test]
+    fn non_canonical_message_can_not_be_exported()
+    {
+        let mut frame = test_frame();
+        frame.message.push(0x00);
+        assert!(to_string(&frame).is_err());
+    }
+
+    fn test_frame() -> Frame
+    {
+        let message = Message {
+            author: PubKey([1u8; 32]),
+            parent: None,
+            stamp: None,
+            compression: Compression::None,
+            content_type: ContentType::Blob,
+            content: vec![1u8, 2u8],
+        };
+        let mut buffer = Vec::new();
+        message.write(&mut buffer).unwrap();
+        Frame {
+            version: 1,
+            message: buffer,
+            signature: Signature([3u8; 64]),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! The message layouts of the versions, shared by the owned [`Message`] and
+//! the borrowed [`MessageRef`] decoders
+//!
+//! [`Message`]: ../message/struct.Message.html
+//! [`MessageRef`]: ../view/struct.MessageRef.html
+
+use std::io::Read;
+
+use errors::{ErrorKind, Result};
+use codec;
+use compression::Compression;
+use message::{Hash, PubKey, Stamp};
+use options::DecodeOptions;
+
+/// Number of items in the message array of the layout `version`
+pub fn array_len(version: u32) -> Result<u32>
+{
+    match version {
+        1 => Ok(4),
+        2 => Ok(6),
+        3 => Ok(7),
+        _ => bail!(ErrorKind::UnsupportedFrameVersion(u64::from(version))),
+    }
+}
+
+/// The layout version of a message array with `items` items
+pub fn version_of_array_len(items: u32) -> Result<u32>
+{
+    match items {
+        4 => Ok(1),
+        6 => Ok(2),
+        7 => Ok(3),
+        _ => bail!(ErrorKind::UnknownMessageLayout(items)),
+    }
+}
+
+/// The layout version of a message with the given optional fields
+pub fn version(compression: Compression, stamp: Option<Stamp>) -> u32
+{
+    match (compression, stamp) {
+        (Compression::None, Some(_)) => 2,
+        (Compression::None, None) => 1,
+        _ => 3,
+    }
+}
+
+/// The fixed size items of a message, the ones before the content type
+pub struct Header {
+    pub author: PubKey,
+    pub parent: Option<Hash>,
+    pub stamp: Option<Stamp>,
+    pub compression: Compression,
+}
+
+/// Reads the items of the layout `version` following the array header, up to the content type
+pub fn read_header<R>(buffer: &mut R, version: u32, options: &DecodeOptions) -> Result<Header>
+    where R: Read
+{
+    let author_bin_length = codec::read_bin_len(buffer, options)?;
+    if author_bin_length != 32 {
+        bail!(ErrorKind::BadPubKeyLength(author_bin_length));
+    }
+    let mut author = [0u8; 32];
+    buffer.read_exact(&mut author)?;
+
+    let parent = Hash::read_with(buffer, options)?;
+
+    let stamp = if version >= 2 {
+        Some(Stamp {
+            sequence: codec::read_uint(buffer, options)?,
+            timestamp: codec::read_uint(buffer, options)?,
+        })
+    } else {
+        None
+    };
+
+    let compression = if version == 3 {
+        Compression::from_code(codec::read_uint(buffer, options)?)?
+    } else {
+        Compression::None
+    };
+
+    Ok(Header { author: PubKey(author), parent, stamp, compression })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_lengths_and_versions_match()
+    {
+        for version in 1..4 {
+            assert_eq!(version_of_array_len(array_len(version).unwrap()).unwrap(), version);
+        }
+        assert!(array_len(0).is_err());
+        assert!(array_len(4).is_err());
+        assert!(version_of_array_len(5).is_err());
+    }
+}
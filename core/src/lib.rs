@@ -1,6 +1,17 @@
+#![recursion_limit = "256"]
+
 extern crate untrusted;
 extern crate ring;
 extern crate rmp;
+extern crate base64;
+extern crate miniz_oxide;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(any(feature = "json", all(test, feature = "serde")))]
+extern crate serde_json;
 
 
 #[macro_use]
@@ -9,43 +20,49 @@ extern crate error_chain;
 pub mod message;
 pub mod frame;
 pub mod signature;
-// pub mod errors;
-
-pub mod errors {
-    error_chain!{
-        foreign_links {
-            // TODO separate error chain for msgpack/codec errors
-            NumValueReadError(::rmp::decode::NumValueReadError);
-            ValueReadError(::rmp::decode::ValueReadError);
-            ValueWriteError(::rmp::encode::ValueWriteError);
+pub mod validator;
+pub mod reader;
+pub mod options;
+pub mod view;
+pub mod content;
+pub mod compression;
+pub mod graph;
+pub mod secret;
+pub mod errors;
 
-            Io(::std::io::Error);
-        }
-    }
-}
+mod codec;
+mod hex;
+mod layout;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "json")]
+pub mod json;
 
 use errors::Result;
 use errors::ResultExt;
 
+pub use secret::SecretKey;
 
 // use ring::signature;
 
-pub type PrivKeyBytes = [u8; 85];
+/// An Ed25519 keypair, as loaded by [`load_key`]
+///
+/// [`load_key`]: fn.load_key.html
+pub type KeyPair = ring::signature::Ed25519KeyPair;
 
-pub fn load_key(bytes: &[u8]) -> Result<ring::signature::Ed25519KeyPair>
+pub fn load_key(bytes: &[u8]) -> Result<KeyPair>
 {
     let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(untrusted::Input::from(bytes))
         .chain_err(|| "Could not load Ed25519 key from PKCS8 bytes")?;
     Ok(key_pair)
 }
 
-pub fn generate_private_key() -> Result<PrivKeyBytes>
+/// Generates a new private key, see [`SecretKey::generate`]
+///
+/// [`SecretKey::generate`]: secret/struct.SecretKey.html#method.generate
+pub fn generate_private_key() -> Result<SecretKey>
 {
-    let randgen = ring::rand::SystemRandom::new();
-    let key_bytes = ring::signature::Ed25519KeyPair::generate_pkcs8(&randgen)
-        .chain_err(|| "Could not generate Ed25519 key!")?;
-
-    Ok(key_bytes)
+    SecretKey::generate()
 }
 
 #[cfg(test)]
@@ -55,7 +72,7 @@ mod tests {
     #[test]
     fn generated_key_can_be_loaded_as_keypair() {
         let privkey = generate_private_key().unwrap();
-        let _keypair: ring::signature::Ed25519KeyPair = load_key(&privkey).unwrap();
+        let _keypair: ring::signature::Ed25519KeyPair = load_key(privkey.as_bytes()).unwrap();
     }
 
     #[test]
@@ -1,46 +1,138 @@
 
+use std::borrow::Cow;
 use std::io;
+use std::hash;
 use std::fmt;
+use std::str::{self, FromStr};
 
-use ::errors::Result;
+use ::errors::{Error, ErrorKind, Result};
+use codec;
+use compression::Compression;
+use content::{self, ContentCodec};
+use hex;
+use layout;
+use options::{DecodeLimits, DecodeOptions};
+use view::MessageRef;
+use ring;
+use base64;
+use KeyPair;
 
 /// An Ed25519 public key, also used as type of author in [`Message`]
+///
+/// Its canonical textual form is `@<base64>.ed25519`, with the standard base64 alphabet.
+/// Parsing also accepts 64 hex digits.
+///
 /// [`Message`]: struct.Message.html
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct PubKey(pub [u8; 32]);
 
 impl fmt::Debug for PubKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for byte in self.0.iter() {
-            write!(f, "{:x} ", byte)?;
+        write!(f, "PubKey({})", self)
+    }
+}
+
+impl fmt::Display for PubKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", PUBKEY_PREFIX, base64::encode(&self.0[..]), PUBKEY_SUFFIX)
+    }
+}
+
+impl FromStr for PubKey {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<PubKey> {
+        let invalid = || ErrorKind::InvalidPubKey(text.to_string());
+
+        if text.starts_with(PUBKEY_PREFIX) && text.ends_with(PUBKEY_SUFFIX) {
+            let encoded = &text[PUBKEY_PREFIX.len()..text.len() - PUBKEY_SUFFIX.len()];
+            let bytes = base64::decode(encoded).map_err(|_| invalid())?;
+            PubKey::from_slice(&bytes)
+        } else {
+            let mut pubkey = [0u8; 32];
+            hex::decode_into(text, &mut pubkey).ok_or_else(invalid)?;
+            Ok(PubKey(pubkey))
         }
-        Ok(())
     }
 }
 
+const PUBKEY_PREFIX: &str = "@";
+const PUBKEY_SUFFIX: &str = ".ed25519";
+
 impl PubKey {
-    /// Creates a new `PubKey` from a slice
-    ///
-    /// TODO: Check slice length?
-    pub fn new(array: &[u8]) -> PubKey
+    /// Creates a new `PubKey` from a slice, which must be 32 bytes long
+    pub fn from_slice(slice: &[u8]) -> Result<PubKey>
     {
-        let mut pubkey = [0u8; 32];
-        for (&x, p) in array.iter().zip(pubkey.iter_mut()) {
-            *p = x;
+        if slice.len() != 32 {
+            bail!(ErrorKind::BadPubKeyLength(slice.len() as u32));
         }
-        PubKey(pubkey)
+        let mut pubkey = [0u8; 32];
+        pubkey.copy_from_slice(slice);
+        Ok(PubKey(pubkey))
+    }
+
+    /// The public key of a keypair, e.g. one loaded by [`load_key`]
+    ///
+    /// [`load_key`]: ../fn.load_key.html
+    pub fn from_keypair(keypair: &KeyPair) -> PubKey
+    {
+        PubKey::from_slice(keypair.public_key_bytes()).expect("Ed25519 public keys are 32 bytes long")
     }
 }
 
 
 /// The SHA-512 digest of the parent [`Message`]'s serialized format
 ///
-/// TODO use fixed-length array
+/// Its textual form is 128 lowercase hex digits, see also [`to_base64`].
+///
 /// [`Message`]: struct.Message.html
-#[derive(Debug, PartialEq)]
-pub struct Hash(pub Vec<u8>);
+/// [`to_base64`]: #method.to_base64
+#[derive(Clone, Copy, Eq, PartialOrd, Ord)]
+pub struct Hash(pub [u8; 64]);
+
+/// Compares in constant time, like [`MessageId`]
+///
+/// [`MessageId`]: struct.MessageId.html
+impl PartialEq for Hash {
+    fn eq(&self, rhs: &Hash) -> bool
+    {
+        ring::constant_time::verify_slices_are_equal(&self.0, &rhs.0).is_ok()
+    }
+}
+
+impl hash::Hash for Hash {
+    fn hash<H: hash::Hasher>(&self, state: &mut H)
+    {
+        self.0.hash(state)
+    }
+}
 
 impl Hash {
+    /// Creates a `Hash` from a slice, which must be 64 bytes long
+    pub fn from_slice(slice: &[u8]) -> Result<Hash>
+    {
+        if slice.len() != 64 {
+            bail!(ErrorKind::BadHashLength(slice.len() as u32));
+        }
+        let mut hash = [0u8; 64];
+        hash.copy_from_slice(slice);
+        Ok(Hash(hash))
+    }
+
+    /// Encodes the hash with the standard base64 alphabet
+    pub fn to_base64(&self) -> String
+    {
+        base64::encode(&self.0[..])
+    }
+
+    /// Decodes a hash from standard base64
+    pub fn from_base64(text: &str) -> Result<Hash>
+    {
+        let bytes = base64::decode(text)
+            .map_err(|_| ErrorKind::InvalidHash(text.to_string()))?;
+        Hash::from_slice(&bytes)
+    }
+
     /// Reads an msgpack-formatted optional `Hash`
     ///
     /// * zero-length array means lack of the `Hash`
@@ -50,22 +142,140 @@ impl Hash {
     pub fn read<R>(buffer: &mut R) -> Result<Option<Hash>>
         where R: io::Read
     {
-        use rmp::decode;
-        let array_length = decode::read_array_len(buffer)?;
+        Hash::read_with(buffer, &DecodeOptions::default())
+    }
+
+    /// Same as [`read`], with the given [`DecodeOptions`]
+    ///
+    /// [`read`]: #method.read
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn read_with<R>(buffer: &mut R, options: &DecodeOptions) -> Result<Option<Hash>>
+        where R: io::Read
+    {
+        let array_length = codec::read_array_len(buffer, options)?;
         if array_length == 0 {
             // zero length array means None
             Ok(None)
         } else {
+            if array_length != 1 {
+                bail!(ErrorKind::UnexpectedArrayLength(1, array_length));
+            }
             // SHA-512 must have length of 64 bytes
-            let hash_length = decode::read_bin_len(buffer)?;
-            assert_eq!(hash_length, 64); // TODO proper error handling
-            let mut hash_buffer = vec![0u8; 64];
+            let hash_length = codec::read_bin_len(buffer, options)?;
+            if hash_length != 64 {
+                bail!(ErrorKind::BadHashLength(hash_length));
+            }
+            let mut hash_buffer = [0u8; 64];
             buffer.read_exact(&mut hash_buffer[..])?;
             Ok(Some(Hash(hash_buffer)))
         }
     }
 }
 
+impl From<Hash> for MessageId {
+    fn from(hash: Hash) -> MessageId {
+        MessageId(hash.0)
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        hex::write(f, &self.0)
+    }
+}
+
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hash({})", self)
+    }
+}
+
+impl FromStr for Hash {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Hash> {
+        let mut hash = [0u8; 64];
+        hex::decode_into(text, &mut hash)
+            .ok_or_else(|| ErrorKind::InvalidHash(text.to_string()))?;
+        Ok(Hash(hash))
+    }
+}
+
+
+/// The canonical identifier of a [`Message`]
+///
+/// It is the SHA-512 digest of the serialized message, the same value that is used
+/// as [`Hash`] in the `parent` field of the next message.
+///
+/// Its textual form is 128 lowercase hex digits.
+///
+/// [`Message`]: struct.Message.html
+/// [`Hash`]: struct.Hash.html
+#[derive(Clone, Copy, Eq, PartialOrd, Ord)]
+pub struct MessageId(pub [u8; 64]);
+
+/// Compares in constant time, so the time taken does not tell how many
+/// leading bytes of a guessed identifier match
+impl PartialEq for MessageId {
+    fn eq(&self, rhs: &MessageId) -> bool
+    {
+        ring::constant_time::verify_slices_are_equal(&self.0, &rhs.0).is_ok()
+    }
+}
+
+impl hash::Hash for MessageId {
+    fn hash<H: hash::Hasher>(&self, state: &mut H)
+    {
+        self.0.hash(state)
+    }
+}
+
+impl MessageId {
+    /// Computes the identifier of a serialized message
+    pub fn of_bytes(message: &[u8]) -> MessageId
+    {
+        let digest = ring::digest::digest(&ring::digest::SHA512, message);
+        let mut id = [0u8; 64];
+        id.copy_from_slice(digest.as_ref());
+        MessageId(id)
+    }
+
+    /// The `Hash` referencing this message as parent
+    pub fn to_hash(&self) -> Hash
+    {
+        Hash(self.0)
+    }
+}
+
+impl From<MessageId> for Hash {
+    fn from(id: MessageId) -> Hash {
+        id.to_hash()
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        hex::write(f, &self.0)
+    }
+}
+
+impl fmt::Debug for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MessageId({})", self)
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<MessageId> {
+        let mut id = [0u8; 64];
+        hex::decode_into(text, &mut id)
+            .ok_or_else(|| ErrorKind::InvalidMessageId(text.to_string()))?;
+        Ok(MessageId(id))
+    }
+}
+
 
 #[derive(Debug, PartialEq)]
 pub enum ContentType {
@@ -77,33 +287,117 @@ impl ContentType {
     pub fn read<R>(buffer: &mut R) -> Result<ContentType>
         where R: io::Read
     {
-        use rmp::decode;
-        let length = decode::read_bin_len(buffer)?;
-        let mut data = vec![0u8; length as usize];
-        buffer.read_exact(&mut data[..])?;
-        Ok(if data.len() == 1 && data[0] == 0u8 {
+        ContentType::read_with(buffer, &DecodeOptions::default())
+    }
+
+    /// Same as [`read`], with the given [`DecodeOptions`]
+    ///
+    /// [`read`]: #method.read
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn read_with<R>(buffer: &mut R, options: &DecodeOptions) -> Result<ContentType>
+        where R: io::Read
+    {
+        Ok(ContentType::from_bytes(codec::read_bin(buffer, options, codec::Field::ContentType)?))
+    }
+
+    /// Creates the content type from its encoded bytes, see [`as_bytes`]
+    ///
+    /// [`as_bytes`]: #method.as_bytes
+    pub fn from_bytes(data: Vec<u8>) -> ContentType
+    {
+        if data == [0u8] {
             ContentType::Blob
         } else {
             ContentType::Custom(data)
-        })
+        }
     }
 
-    pub fn write(&self, buffer: &mut Vec<u8>) -> Result<u32>
+    /// Encodes the content type and returns the number of written bytes
+    pub fn write<W>(&self, buffer: &mut W) -> Result<usize>
+        where W: io::Write + ?Sized
     {
         use rmp::encode;
+        let mut writer = codec::CountingWriter::new(buffer);
+        encode::write_bin(&mut writer, self.as_bytes())?;
+        Ok(writer.count())
+    }
+
+    /// Number of bytes written by [`write`]
+    ///
+    /// [`write`]: #method.write
+    pub fn encoded_len(&self) -> usize
+    {
+        codec::bin_len(self.as_bytes().len())
+    }
+
+    /// The encoded bytes of the content type, a single zero byte for `Blob`
+    pub fn as_bytes(&self) -> &[u8]
+    {
+        match *self {
+            ContentType::Blob => &[0u8],
+            ContentType::Custom(ref data) => data,
+        }
+    }
+}
 
-        match self {
-            &ContentType::Blob => encode::write_bin(buffer, &[0u8])?,
-            &ContentType::Custom(ref data) => encode::write_bin(buffer, data)?
+/// Shows `<blob>`, a textual identifier as is and any other identifier as `<hex:...>`
+///
+/// A textual identifier is a non-empty printable ASCII one, e.g. `post`. The
+/// empty identifier and the ones starting with `<` are shown in the hex form,
+/// so two different content types are never shown the same way, e.g. in the
+/// output of `ku`: the blob type is `<blob>`, while `blob` is a custom type.
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentType::Blob => f.write_str("<blob>"),
+            ContentType::Custom(ref data) => match str::from_utf8(data) {
+                Ok(text) if !text.is_empty() && !text.starts_with('<') && text.chars().all(|c| c.is_ascii_graphic()) =>
+                    f.write_str(text),
+                _ => {
+                    f.write_str("<hex:")?;
+                    hex::write(f, data)?;
+                    f.write_str(">")
+                },
+            },
         }
+    }
+}
+
+/// Position and creation time of a [`Message`] in its feed
+///
+/// Only version 2 and 3 messages carry it, see [`Message::write`].
+///
+/// [`Message`]: struct.Message.html
+/// [`Message::write`]: struct.Message.html#method.write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct Stamp {
+    /// Zero-based index of the message in the feed of its author
+    pub sequence: u64,
+
+    /// Creation time in milliseconds since the Unix epoch, as claimed by the author
+    pub timestamp: u64,
+}
 
-        Ok(0u32)
+impl Stamp {
+    /// Stamps the message at `sequence` with the current time
+    pub fn now(sequence: u64) -> Stamp
+    {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Stamp {
+            sequence,
+            timestamp: elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+        }
     }
 }
 
 /// The actual message
 ///
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Message {
     /// The Ed25519 public key of the author
     pub author: PubKey,
@@ -114,85 +408,247 @@ pub struct Message {
     /// All child nodes must fill this value.
     pub parent: Option<Hash>,
 
+    /// Sequence number and timestamp, `None` for version 1 messages
+    pub stamp: Option<Stamp>,
+
+    /// Compression of the `content`, anything but `None` needs a version 3 message
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compression: Compression,
+
     /// Application-specific type identifier
     ///
     /// It determines how the content should be interpreted
     pub content_type: ContentType,
 
     /// The actual payload of the `Message`
+    #[cfg_attr(feature = "serde", serde(with = "::serde_impls::base64_bytes"))]
     pub content: Vec<u8>,
 }
 
 impl Message {
+    /// Version of the message layout: 3 if its content is compressed,
+    /// 2 if it has a [`Stamp`], 1 otherwise
+    ///
+    /// The [`Frame`] wrapping the message has the same version.
+    ///
+    /// [`Stamp`]: struct.Stamp.html
+    /// [`Frame`]: ../frame/struct.Frame.html
+    pub fn version(&self) -> u32
+    {
+        layout::version(self.compression, self.stamp)
+    }
+
     /// Encodes the Message in the msgpack format
     ///
-    /// The format is: an array with 4 items:
+    /// The version 1 format is an array with 4 items:
     ///
     /// 1. author's public key (32 bytes binary)
     /// 2. hash of parent node, see [`Hash`] for details
     /// 3. content type (binary, variable length)
     /// 4. content: (binary, variable length)
     ///
+    /// The version 2 format is an array with 6 items, the [`Stamp`]
+    /// follows the parent:
+    ///
+    /// 1. author's public key (32 bytes binary)
+    /// 2. hash of parent node
+    /// 3. sequence number (unsigned integer)
+    /// 4. timestamp (unsigned integer)
+    /// 5. content type (binary, variable length)
+    /// 6. content: (binary, variable length)
+    ///
+    /// The version 3 format is an array with 7 items, the [`Compression`]
+    /// of the content follows the stamp, which is mandatory:
+    ///
+    /// 1. author's public key (32 bytes binary)
+    /// 2. hash of parent node
+    /// 3. sequence number (unsigned integer)
+    /// 4. timestamp (unsigned integer)
+    /// 5. compression method (unsigned integer, 1: deflate)
+    /// 6. content type (binary, variable length)
+    /// 7. compressed content: (binary, variable length)
+    ///
+    /// Returns the number of written bytes.
+    ///
     /// [`Hash`]: struct.Hash.html
-    pub fn write(&self, buffer: &mut Vec<u8>) -> Result<u32>
+    /// [`Stamp`]: struct.Stamp.html
+    /// [`Compression`]: ../compression/enum.Compression.html
+    pub fn write<W>(&self, buffer: &mut W) -> Result<usize>
+        where W: io::Write + ?Sized
     {
         use rmp::encode;
-        encode::write_array_len(buffer, 4)?;
+        let version = self.version();
+        if version == 3 && self.stamp.is_none() {
+            bail!("a message with compressed content must have a stamp");
+        }
+        let mut writer = codec::CountingWriter::new(buffer);
+        encode::write_array_len(&mut writer, layout::array_len(version)?)?;
+
+        encode::write_bin(&mut writer, self.author.0.as_ref())?;
+
+        self.write_parent(&mut writer)?;
+        if let Some(ref stamp) = self.stamp {
+            encode::write_uint(&mut writer, stamp.sequence)?;
+            encode::write_uint(&mut writer, stamp.timestamp)?;
+        }
+        if version == 3 {
+            encode::write_uint(&mut writer, self.compression.code())?;
+        }
+        self.content_type.write(&mut writer)?;
+
+        encode::write_bin(&mut writer, self.content.as_ref())?;
+
+        Ok(writer.count())
+    }
 
-        encode::write_bin(buffer, self.author.0.as_ref())?;
+    /// Number of bytes written by [`write`]
+    ///
+    /// [`write`]: #method.write
+    pub fn encoded_len(&self) -> usize
+    {
+        let parent_len = match self.parent {
+            Some(_) => codec::array_header_len(1) + codec::bin_len(64),
+            None => codec::array_header_len(0),
+        };
+        let stamp_len = match self.stamp {
+            Some(ref stamp) => codec::uint_len(stamp.sequence) + codec::uint_len(stamp.timestamp),
+            None => 0,
+        };
+        let compression_len = match self.compression {
+            Compression::None => 0,
+            compression => codec::uint_len(compression.code()),
+        };
+        codec::array_header_len(layout::array_len(self.version()).expect("Message versions have a layout") as usize)
+            + codec::bin_len(32)
+            + parent_len
+            + stamp_len
+            + compression_len
+            + self.content_type.encoded_len()
+            + codec::bin_len(self.content.len())
+    }
 
-        self.write_parent(buffer)?;
-        self.content_type.write(buffer)?;
+    /// A borrowed [`MessageRef`] of this message
+    ///
+    /// [`MessageRef`]: ../view/struct.MessageRef.html
+    pub fn view(&self) -> MessageRef<'_>
+    {
+        MessageRef {
+            author: self.author,
+            parent: self.parent,
+            stamp: self.stamp,
+            compression: self.compression,
+            content_type: self.content_type.as_bytes(),
+            content: &self.content,
+        }
+    }
+
+    /// The canonical identifier of the message, see [`MessageId`]
+    ///
+    /// [`MessageId`]: struct.MessageId.html
+    pub fn id(&self) -> Result<MessageId>
+    {
+        let mut writer = codec::DigestWriter::new(io::sink());
+        self.write(&mut writer)?;
+        let (_, digest) = writer.finish();
+        let mut id = [0u8; 64];
+        id.copy_from_slice(digest.as_ref());
+        Ok(MessageId(id))
+    }
 
-        encode::write_bin(buffer, self.content.as_ref())?;
+    /// The content of the message, decompressed if needed
+    ///
+    /// Fails with [`ErrorKind::DecompressionLimitExceeded`] if the decompressed
+    /// content would be longer than `limits.max_decompressed_len`.
+    ///
+    /// [`ErrorKind::DecompressionLimitExceeded`]: ../errors/enum.ErrorKind.html
+    pub fn decompressed_content(&self, limits: &DecodeLimits) -> Result<Cow<'_, [u8]>>
+    {
+        self.compression.decompress(&self.content, limits)
+    }
 
-        Ok(0u32)
+    /// Decodes the content as `T`, the content type must be the one of `T`
+    ///
+    /// A compressed content is decompressed first, within the default [`DecodeLimits`].
+    /// See the [`content`] module about typed contents.
+    ///
+    /// [`content`]: ../content/index.html
+    /// [`DecodeLimits`]: ../options/struct.DecodeLimits.html
+    pub fn decode_content<T>(&self) -> Result<T>
+        where T: ContentCodec
+    {
+        let content = self.decompressed_content(&DecodeLimits::default())?;
+        content::decode(self.content_type.as_bytes(), &content)
     }
 
-    /// Encodes the Message from the msgpack format
-    fn write_parent(&self, buffer: &mut Vec<u8>) -> Result<u32>
+    /// Encodes the optional parent `Hash` in the msgpack format
+    fn write_parent<W>(&self, buffer: &mut W) -> Result<()>
+        where W: io::Write
     {
         use rmp::encode;
         match self.parent {
             Some(ref hash) => {
                 encode::write_array_len(buffer, 1)?;
-                encode::write_bin(buffer, hash.0.as_ref())?;
+                encode::write_bin(buffer, &hash.0[..])?;
             },
             None => { encode::write_array_len(buffer, 0)?; }
         };
 
-        Ok(0u32)
+        Ok(())
     }
 
+    /// Decodes a Message of any supported version, see [`write`]
+    ///
+    /// [`write`]: #method.write
     pub fn read<R>(buffer: &mut R) -> Result<Message>
         where R: io::Read
     {
-        use rmp::decode;
-        let _array_size = decode::read_array_len(buffer)?;
-        assert_eq!(_array_size, 4); // TODO error handling TODO must be 4
-
-        let author_bin_length = decode::read_bin_len(buffer)?;
-        assert_eq!(author_bin_length, 32); // TODO error handling
-
-        let mut author_buffer = [0u8; 32];
-        buffer.read_exact(&mut author_buffer)?;
-
-        let parent_hash: Option<Hash> = Hash::read(buffer)?;
+        Message::read_with(buffer, &DecodeOptions::default())
+    }
 
-        let content_type = ContentType::read(buffer)?;
+    /// Same as [`read`], with the given [`DecodeOptions`]
+    ///
+    /// [`read`]: #method.read
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn read_with<R>(buffer: &mut R, options: &DecodeOptions) -> Result<Message>
+        where R: io::Read
+    {
+        let items = codec::read_array_len(buffer, options)?;
+        let version = layout::version_of_array_len(items)?;
+        Message::read_body(buffer, version, options)
+    }
 
-        let content_length = decode::read_bin_len(buffer)?;
-        let mut content_vec = vec![0u8; content_length as usize];
-        buffer.read_exact(&mut content_vec[..])?;
+    /// Decodes a Message that must have the given layout `version`
+    pub fn read_version<R>(buffer: &mut R, version: u32, options: &DecodeOptions) -> Result<Message>
+        where R: io::Read
+    {
+        codec::read_array_len_exact(buffer, layout::array_len(version)?, options)?;
+        Message::read_body(buffer, version, options)
+    }
 
-        let msg = Message {
-            author: PubKey(author_buffer),
-            parent: parent_hash,
-            content_type: content_type,
-            content: content_vec
-        };
+    /// Decodes a Message from a complete buffer
+    ///
+    /// In strict mode trailing bytes after the message are rejected.
+    pub fn decode(bytes: &[u8], options: &DecodeOptions) -> Result<Message>
+    {
+        let mut remaining = bytes;
+        let message = Message::read_with(&mut remaining, options)?;
+        codec::check_consumed(remaining, options)?;
+        Ok(message)
+    }
 
-        Ok(msg)
+    /// Decodes the items following the array header
+    fn read_body<R>(buffer: &mut R, version: u32, options: &DecodeOptions) -> Result<Message>
+        where R: io::Read
+    {
+        let header = layout::read_header(buffer, version, options)?;
+        Ok(Message {
+            author: header.author,
+            parent: header.parent,
+            stamp: header.stamp,
+            compression: header.compression,
+            content_type: ContentType::read_with(buffer, options)?,
+            content: codec::read_bin(buffer, options, codec::Field::Content)?,
+        })
     }
 
 }
@@ -203,6 +659,7 @@ mod tests {
 
     mod encoding_and_decoding {
         use super::*;
+        use errors::Error;
 
         #[test]
         fn should_result_the_same_message()
@@ -210,6 +667,8 @@ mod tests {
             let message = Message {
                 author: PubKey([1u8; 32]),
                 parent: None,
+                stamp: None,
+                compression: Compression::None,
                 content_type: ContentType::Custom(vec![43u8]),
                 content: vec![255u8, 254u8],
             };
@@ -228,7 +687,9 @@ mod tests {
         {
             let message = Message {
                 author: PubKey([1u8; 32]),
-                parent: Some(Hash(vec![2u8; 64])),
+                parent: Some(Hash([2u8; 64])),
+                stamp: None,
+                compression: Compression::None,
                 content_type: ContentType::Custom(vec![42u8]),
                 content: vec![255u8, 255u8],
             };
@@ -242,6 +703,8 @@ mod tests {
             let message = Message {
                 author: PubKey([1u8; 32]),
                 parent: None,
+                stamp: None,
+                compression: Compression::None,
                 content_type: ContentType::Custom(vec![42u8]),
                 content: vec![42u8, 44u8],
             };
@@ -249,6 +712,36 @@ mod tests {
             assert_eq!(message.parent, decoded_message.parent);
         }
 
+        #[test]
+        fn bad_author_length_is_rejected()
+        {
+            let buffer = vec![0x94, 0xc4, 0x02, 0x01, 0x02];
+            match Message::read(&mut io::Cursor::new(buffer)) {
+                Err(Error(ErrorKind::BadPubKeyLength(2), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn bad_parent_hash_length_is_rejected()
+        {
+            let buffer = vec![0x91, 0xc4, 0x03, 0x01, 0x02, 0x03];
+            match Hash::read(&mut io::Cursor::new(buffer)) {
+                Err(Error(ErrorKind::BadHashLength(3), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn parent_array_with_more_items_is_rejected()
+        {
+            let buffer = vec![0x92];
+            match Hash::read(&mut io::Cursor::new(buffer)) {
+                Err(Error(ErrorKind::UnexpectedArrayLength(1, 2), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
         fn encode_decode(message: &Message) -> Message {
             let mut buffer: Vec<u8> = Vec::new();
             message.write(&mut buffer).expect("Write failed");
@@ -268,4 +761,418 @@ mod tests {
             println!("]\n");
         }
     }
+
+    mod encoded_length {
+        use super::*;
+
+        #[test]
+        fn written_length_is_returned_and_predicted()
+        {
+            let messages = vec![
+                Message {
+                    author: PubKey([1u8; 32]),
+                    parent: None,
+                    stamp: None,
+                    compression: Compression::None,
+                    content_type: ContentType::Blob,
+                    content: vec![],
+                },
+                Message {
+                    author: PubKey([1u8; 32]),
+                    parent: Some(Hash([2u8; 64])),
+                    stamp: None,
+                    compression: Compression::None,
+                    content_type: ContentType::Custom(vec![7u8; 300]),
+                    content: vec![3u8; 70000],
+                },
+            ];
+
+            for message in messages {
+                let mut buffer = Vec::new();
+                let written = message.write(&mut buffer).unwrap();
+                assert_eq!(written, buffer.len());
+                assert_eq!(message.encoded_len(), buffer.len());
+            }
+        }
+
+        #[test]
+        fn message_can_be_written_to_any_writer()
+        {
+            let message = Message {
+                author: PubKey([1u8; 32]),
+                parent: None,
+                stamp: None,
+                compression: Compression::None,
+                content_type: ContentType::Blob,
+                content: vec![4u8; 10],
+            };
+            let mut buffer = [0u8; 128];
+            let written = message.write(&mut io::Cursor::new(&mut buffer[..])).unwrap();
+
+            let decoded = Message::read(&mut &buffer[..written]).unwrap();
+            assert_eq!(decoded.content, message.content);
+        }
+    }
+
+    mod versions {
+        use super::*;
+        use errors::Error;
+
+        #[test]
+        fn stamp_makes_a_version_2_message()
+        {
+            let mut message = test_message();
+            assert_eq!(message.version(), 1);
+            message.stamp = Some(Stamp { sequence: 7, timestamp: 1_500_000_000_000 });
+            assert_eq!(message.version(), 2);
+
+            let mut buffer = Vec::new();
+            message.write(&mut buffer).unwrap();
+            assert_eq!(buffer[0], 0x96);
+            assert_eq!(message.encoded_len(), buffer.len());
+
+            let decoded = Message::read(&mut &buffer[..]).unwrap();
+            assert_eq!(decoded.stamp, message.stamp);
+            assert_eq!(decoded.content, message.content);
+        }
+
+        #[test]
+        fn version_1_layout_is_still_decoded()
+        {
+            let mut buffer = Vec::new();
+            test_message().write(&mut buffer).unwrap();
+            assert_eq!(buffer[0], 0x94);
+
+            assert!(Message::read(&mut &buffer[..]).unwrap().stamp.is_none());
+            assert!(Message::read_version(&mut &buffer[..], 1, &DecodeOptions::default()).is_ok());
+            assert!(Message::read_version(&mut &buffer[..], 2, &DecodeOptions::default()).is_err());
+        }
+
+        #[test]
+        fn compression_makes_a_version_3_message()
+        {
+            let mut message = test_message();
+            message.stamp = Some(Stamp { sequence: 7, timestamp: 1_500_000_000_000 });
+            message.compression = Compression::Deflate;
+            message.content = Compression::Deflate.compress(&[3u8; 100]).into_owned();
+            assert_eq!(message.version(), 3);
+
+            let mut buffer = Vec::new();
+            message.write(&mut buffer).unwrap();
+            assert_eq!(buffer[0], 0x97);
+            assert_eq!(message.encoded_len(), buffer.len());
+
+            let decoded = Message::decode(&buffer, &DecodeOptions::strict()).unwrap();
+            assert_eq!(decoded.stamp, message.stamp);
+            assert_eq!(decoded.compression, Compression::Deflate);
+            assert_eq!(decoded.content, message.content);
+            assert_eq!(decoded.decompressed_content(&DecodeLimits::default()).unwrap().as_ref(), &[3u8; 100][..]);
+            assert!(Message::read_version(&mut &buffer[..], 2, &DecodeOptions::default()).is_err());
+        }
+
+        #[test]
+        fn compressed_message_must_be_stamped()
+        {
+            let mut message = test_message();
+            message.compression = Compression::Deflate;
+            assert!(message.write(&mut Vec::new()).is_err());
+        }
+
+        #[test]
+        fn version_3_layout_must_name_a_known_compression()
+        {
+            let mut message = test_message();
+            message.stamp = Some(Stamp { sequence: 7, timestamp: 0 });
+            message.compression = Compression::Deflate;
+            let mut buffer = Vec::new();
+            message.write(&mut buffer).unwrap();
+            // array header, author, parent, sequence and timestamp precede the compression
+            let position = 1 + 34 + 67 + 1 + 1;
+            assert_eq!(buffer[position], 1);
+
+            for &code in &[0u8, 2u8] {
+                buffer[position] = code;
+                match Message::read(&mut &buffer[..]) {
+                    Err(Error(ErrorKind::UnknownCompression(found), _)) => assert_eq!(found, u64::from(code)),
+                    other => panic!("Unexpected result: {:?}", other),
+                }
+            }
+        }
+
+        #[test]
+        fn compression_changes_the_message_id()
+        {
+            let mut stamped = test_message();
+            stamped.stamp = Some(Stamp { sequence: 0, timestamp: 0 });
+            let mut compressed = test_message();
+            compressed.stamp = stamped.stamp;
+            compressed.compression = Compression::Deflate;
+            assert_ne!(stamped.id().unwrap(), compressed.id().unwrap());
+        }
+
+        #[test]
+        fn unknown_layout_is_rejected()
+        {
+            let buffer = [0x95, 0xc4, 0x00];
+            match Message::read(&mut &buffer[..]) {
+                Err(Error(ErrorKind::UnknownMessageLayout(5), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn stamp_changes_the_message_id()
+        {
+            let mut stamped = test_message();
+            stamped.stamp = Some(Stamp { sequence: 0, timestamp: 0 });
+            assert_ne!(stamped.id().unwrap(), test_message().id().unwrap());
+        }
+
+        fn test_message() -> Message
+        {
+            Message {
+                author: PubKey([1u8; 32]),
+                parent: Some(Hash([2u8; 64])),
+                stamp: None,
+                compression: Compression::None,
+                content_type: ContentType::Blob,
+                content: vec![3u8, 4u8],
+            }
+        }
+    }
+
+    mod canonical_encoding {
+        use super::*;
+        use errors::Error;
+
+        #[test]
+        fn written_message_is_canonical()
+        {
+            let mut buffer = Vec::new();
+            test_message().write(&mut buffer).unwrap();
+
+            let decoded = Message::decode(&buffer, &DecodeOptions::strict()).unwrap();
+            assert_eq!(decoded.stamp, test_message().stamp);
+        }
+
+        #[test]
+        fn non_minimal_length_is_rejected_only_in_strict_mode()
+        {
+            let mut buffer = Vec::new();
+            test_message().write(&mut buffer).unwrap();
+            // the content is the last item: bin8 with 2 bytes, re-encoded as bin16
+            let content_start = buffer.len() - 4;
+            assert_eq!(buffer[content_start], 0xc4);
+            buffer.splice(content_start..content_start + 2, vec![0xc5, 0x00, 0x02]);
+
+            match Message::decode(&buffer, &DecodeOptions::strict()) {
+                Err(Error(ErrorKind::NonCanonicalEncoding(0xc5), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            assert!(Message::decode(&buffer, &DecodeOptions::default()).is_ok());
+        }
+
+        #[test]
+        fn trailing_bytes_are_rejected_only_in_strict_mode()
+        {
+            let mut buffer = Vec::new();
+            test_message().write(&mut buffer).unwrap();
+            buffer.push(0x00);
+
+            match Message::decode(&buffer, &DecodeOptions::strict()) {
+                Err(Error(ErrorKind::TrailingBytes(1), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            assert!(Message::decode(&buffer, &DecodeOptions::default()).is_ok());
+        }
+
+        fn test_message() -> Message
+        {
+            Message {
+                author: PubKey([1u8; 32]),
+                parent: Some(Hash([2u8; 64])),
+                stamp: Some(Stamp { sequence: 200, timestamp: 1_500_000_000_000 }),
+                compression: Compression::None,
+                content_type: ContentType::Custom(b"post".to_vec()),
+                content: vec![3u8, 4u8],
+            }
+        }
+    }
+
+    mod message_id {
+        use super::*;
+
+        #[test]
+        fn id_is_the_sha512_of_the_serialized_message()
+        {
+            let message = test_message();
+            let mut buffer = Vec::new();
+            message.write(&mut buffer).unwrap();
+            let digest = ring::digest::digest(&ring::digest::SHA512, &buffer);
+
+            assert_eq!(message.id().unwrap().0[..], digest.as_ref()[..]);
+        }
+
+        #[test]
+        fn id_is_the_parent_hash_of_the_next_message()
+        {
+            let id = test_message().id().unwrap();
+            let hash: Hash = id.into();
+            assert_eq!(hash.0[..], id.0[..]);
+            assert_eq!(MessageId::from(hash), id);
+        }
+
+        #[test]
+        fn id_can_be_displayed_and_parsed()
+        {
+            let id = test_message().id().unwrap();
+            let text = id.to_string();
+            assert_eq!(text.len(), 128);
+            assert!(text.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+            assert_eq!(text.parse::<MessageId>().unwrap(), id);
+        }
+
+        #[test]
+        fn invalid_id_text_is_rejected()
+        {
+            assert!("".parse::<MessageId>().is_err());
+            assert!("abcd".parse::<MessageId>().is_err());
+            assert!(format!("{}x", "0".repeat(127)).parse::<MessageId>().is_err());
+        }
+
+        fn test_message() -> Message
+        {
+            Message {
+                author: PubKey([1u8; 32]),
+                parent: Some(Hash([2u8; 64])),
+                stamp: None,
+                compression: Compression::None,
+                content_type: ContentType::Blob,
+                content: vec![3u8, 4u8],
+            }
+        }
+    }
+
+    mod hash {
+        use super::*;
+        use std::collections::BTreeSet;
+
+        #[test]
+        fn hash_can_only_be_created_from_64_bytes()
+        {
+            assert_eq!(Hash::from_slice(&[7u8; 64]).unwrap(), Hash([7u8; 64]));
+            match Hash::from_slice(&[7u8; 3]) {
+                Err(Error(ErrorKind::BadHashLength(3), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn equal_hashes_and_ids_are_equal_in_sets()
+        {
+            use std::collections::HashSet;
+            let mut last_byte_differs = [7u8; 64];
+            last_byte_differs[63] = 8;
+            assert!(Hash([7u8; 64]) != Hash(last_byte_differs));
+            assert!(MessageId([7u8; 64]) != MessageId(last_byte_differs));
+
+            let ids: HashSet<MessageId> = [MessageId([7u8; 64]), MessageId([7u8; 64])].iter().cloned().collect();
+            assert_eq!(ids.len(), 1);
+            assert!(ids.contains(&MessageId([7u8; 64])));
+        }
+
+        #[test]
+        fn hash_can_be_displayed_and_parsed_as_hex()
+        {
+            let hash = Hash([0xab; 64]);
+            assert_eq!(hash.to_string(), "ab".repeat(64));
+            assert_eq!(hash.to_string().parse::<Hash>().unwrap(), hash);
+            assert!("abab".parse::<Hash>().is_err());
+        }
+
+        #[test]
+        fn hash_can_be_encoded_as_base64()
+        {
+            let hash = Hash([0xff; 64]);
+            let text = hash.to_base64();
+            assert_eq!(text.len(), 88);
+            assert_eq!(Hash::from_base64(&text).unwrap(), hash);
+            assert!(Hash::from_base64("////").is_err());
+            assert!(Hash::from_base64("not base64!").is_err());
+        }
+
+        #[test]
+        fn hash_can_be_used_as_ordered_key()
+        {
+            let mut set = BTreeSet::new();
+            set.insert(Hash([2u8; 64]));
+            set.insert(Hash([1u8; 64]));
+            set.insert(Hash([2u8; 64]));
+            assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![Hash([1u8; 64]), Hash([2u8; 64])]);
+        }
+    }
+
+    mod content_type {
+        use super::*;
+
+        #[test]
+        fn content_types_are_displayed_unambiguously()
+        {
+            assert_eq!(ContentType::Blob.to_string(), "<blob>");
+            assert_eq!(ContentType::Custom(b"blob".to_vec()).to_string(), "blob");
+            assert_eq!(ContentType::Custom(b"<blob>".to_vec()).to_string(), "<hex:3c626c6f623e>");
+            assert_eq!(ContentType::Custom(vec![0x00, 0xff]).to_string(), "<hex:00ff>");
+            assert_eq!(ContentType::Custom(vec![]).to_string(), "<hex:>");
+        }
+    }
+
+    mod pubkey {
+        use super::*;
+        use ::{generate_private_key, load_key};
+
+        #[test]
+        fn pubkey_can_only_be_created_from_32_bytes()
+        {
+            assert_eq!(PubKey::from_slice(&[1u8; 32]).unwrap(), PubKey([1u8; 32]));
+            match PubKey::from_slice(&[1u8; 33]) {
+                Err(Error(ErrorKind::BadPubKeyLength(33), _)) => {},
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn pubkey_is_displayed_in_canonical_form()
+        {
+            let pubkey = PubKey([0u8; 32]);
+            assert_eq!(pubkey.to_string(), "@AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=.ed25519");
+            assert_eq!(format!("{:?}", pubkey), "PubKey(@AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=.ed25519)");
+        }
+
+        #[test]
+        fn pubkey_can_be_parsed_from_canonical_form_and_hex()
+        {
+            let pubkey = PubKey([0xf0; 32]);
+            assert_eq!(pubkey.to_string().parse::<PubKey>().unwrap(), pubkey);
+            assert_eq!("f0".repeat(32).parse::<PubKey>().unwrap(), pubkey);
+        }
+
+        #[test]
+        fn invalid_pubkey_text_is_rejected()
+        {
+            assert!("".parse::<PubKey>().is_err());
+            assert!("@.ed25519".parse::<PubKey>().is_err());
+            assert!("@AAAA.ed25519".parse::<PubKey>().is_err());
+            assert!("@not base64!.ed25519".parse::<PubKey>().is_err());
+            assert!("f0".repeat(31).parse::<PubKey>().is_err());
+        }
+
+        #[test]
+        fn pubkey_can_be_derived_from_keypair()
+        {
+            let privkey = generate_private_key().unwrap();
+            let keypair = load_key(privkey.as_bytes()).unwrap();
+            let pubkey = PubKey::from_keypair(&keypair);
+            assert_eq!(pubkey.0[..], keypair.public_key_bytes()[..]);
+        }
+    }
 }
@@ -0,0 +1,103 @@
+/// Controls how strictly the decoders treat their input
+///
+/// The default is lenient: any msgpack encoding of a value is accepted,
+/// as long as it can be decoded.
+///
+/// In strict mode only the canonical encoding is accepted, i.e. the one our
+/// encoders produce: every length and integer uses the shortest marker, and
+/// a complete buffer must not have trailing bytes. So every [`Message`] has
+/// exactly one valid byte form and its [`MessageId`] is stable across
+/// implementations.
+///
+/// The [`DecodeLimits`] are applied in both modes.
+///
+/// [`Message`]: ../message/struct.Message.html
+/// [`MessageId`]: ../message/struct.MessageId.html
+/// [`DecodeLimits`]: struct.DecodeLimits.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Reject non-minimal encodings and trailing bytes
+    pub strict: bool,
+
+    /// Upper bounds of the variable length fields
+    pub limits: DecodeLimits,
+}
+
+impl DecodeOptions {
+    /// Options accepting only the canonical encoding, with the default limits
+    pub fn strict() -> DecodeOptions
+    {
+        DecodeOptions { strict: true, ..DecodeOptions::default() }
+    }
+}
+
+/// Default of [`DecodeLimits::max_content_len`], 1 MiB
+///
+/// [`DecodeLimits::max_content_len`]: struct.DecodeLimits.html#structfield.max_content_len
+pub const DEFAULT_MAX_CONTENT_LEN: u32 = 1024 * 1024;
+
+/// Default of [`DecodeLimits::max_content_type_len`]
+///
+/// [`DecodeLimits::max_content_type_len`]: struct.DecodeLimits.html#structfield.max_content_type_len
+pub const DEFAULT_MAX_CONTENT_TYPE_LEN: u32 = 256;
+
+/// Default of [`DecodeLimits::max_message_size`]: a message with the largest
+/// default content and content type fits in it
+///
+/// [`DecodeLimits::max_message_size`]: struct.DecodeLimits.html#structfield.max_message_size
+pub const DEFAULT_MAX_MESSAGE_SIZE: u32 = DEFAULT_MAX_CONTENT_LEN + 1024;
+
+/// Default of [`DecodeLimits::max_decompressed_len`], 16 MiB
+///
+/// [`DecodeLimits::max_decompressed_len`]: struct.DecodeLimits.html#structfield.max_decompressed_len
+pub const DEFAULT_MAX_DECOMPRESSED_LEN: u32 = 16 * DEFAULT_MAX_CONTENT_LEN;
+
+/// Upper bounds of the variable length fields, in bytes
+///
+/// The lengths are read from the untrusted input, they are checked against
+/// these limits before the data is read, so a short input can not make the
+/// decoder allocate a huge buffer. Exceeding a limit is reported as
+/// [`ErrorKind::LimitExceeded`].
+///
+/// [`ErrorKind::LimitExceeded`]: ../errors/enum.ErrorKind.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Size of the serialized message in a frame
+    pub max_message_size: u32,
+
+    /// Length of the content type of a message
+    pub max_content_type_len: u32,
+
+    /// Length of the content of a message
+    pub max_content_len: u32,
+
+    /// Length of a compressed content after decompression, see [`Compression`]
+    ///
+    /// [`Compression`]: ../compression/enum.Compression.html
+    pub max_decompressed_len: u32,
+}
+
+impl DecodeLimits {
+    /// No limits besides the format's own, only for trusted input
+    pub fn unlimited() -> DecodeLimits
+    {
+        DecodeLimits {
+            max_message_size: !0,
+            max_content_type_len: !0,
+            max_content_len: !0,
+            max_decompressed_len: !0,
+        }
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> DecodeLimits
+    {
+        DecodeLimits {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_content_type_len: DEFAULT_MAX_CONTENT_TYPE_LEN,
+            max_content_len: DEFAULT_MAX_CONTENT_LEN,
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::io::{self, Read};
+
+use errors::{ErrorKind, Result, ResultExt};
+use frame::Frame;
+use options::DecodeOptions;
+
+/// Reads concatenated [`Frame`]s from a byte stream, e.g. a file or a socket
+///
+/// The iterator ends at a clean end of stream, i.e. when the stream ends
+/// exactly at a frame boundary. Partial trailing data is reported as
+/// [`ErrorKind::TruncatedFrame`], any other undecodable data as
+/// [`ErrorKind::CorruptFrame`], both with the byte offset where the frame starts.
+/// After an error the iterator ends, as the position of the next frame is unknown.
+///
+/// Frames are decoded leniently by default, see [`with_options`] for strict decoding.
+///
+/// [`Frame`]: ../frame/struct.Frame.html
+/// [`ErrorKind::TruncatedFrame`]: ../errors/enum.ErrorKind.html
+/// [`ErrorKind::CorruptFrame`]: ../errors/enum.ErrorKind.html
+/// [`with_options`]: #method.with_options
+pub struct FrameReader<R> {
+    inner: CountingReader<R>,
+    options: DecodeOptions,
+    failed: bool,
+}
+
+impl<R> FrameReader<R>
+    where R: Read
+{
+    pub fn new(reader: R) -> FrameReader<R>
+    {
+        FrameReader::with_options(reader, DecodeOptions::default())
+    }
+
+    /// Creates a reader decoding the frames with the given [`DecodeOptions`]
+    ///
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn with_options(reader: R, options: DecodeOptions) -> FrameReader<R>
+    {
+        FrameReader {
+            inner: CountingReader { inner: reader, offset: 0, eof: false },
+            options,
+            failed: false,
+        }
+    }
+
+    /// Number of bytes consumed from the stream so far
+    pub fn offset(&self) -> u64
+    {
+        self.inner.offset
+    }
+
+    pub fn into_inner(self) -> R
+    {
+        self.inner.inner
+    }
+
+    fn read_frame(&mut self) -> Option<Result<Frame>>
+    {
+        let start = self.inner.offset;
+
+        let mut first_byte = [0u8; 1];
+        match read_full(&mut self.inner, &mut first_byte) {
+            Ok(0) => return None,
+            Ok(_) => {},
+            Err(e) => return Some(Err(e.into())),
+        }
+
+        let result = Frame::read_with(&mut (&first_byte[..]).chain(&mut self.inner), &self.options);
+        Some(match result {
+            Ok(frame) => Ok(frame),
+            Err(e) => {
+                let kind = if self.inner.eof {
+                    ErrorKind::TruncatedFrame(start)
+                } else {
+                    ErrorKind::CorruptFrame(start)
+                };
+                Err(e).chain_err(|| kind)
+            },
+        })
+    }
+}
+
+impl<R> Iterator for FrameReader<R>
+    where R: Read
+{
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Result<Frame>>
+    {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_frame();
+        if let Some(Err(_)) = result {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+/// Reads into `buffer` until it is full or the stream ends
+fn read_full<R>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize>
+    where R: Read
+{
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Counts the consumed bytes and remembers if the end of the stream was reached
+struct CountingReader<R> {
+    inner: R,
+    offset: u64,
+    eof: bool,
+}
+
+impl<R> Read for CountingReader<R>
+    where R: Read
+{
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>
+    {
+        let read = self.inner.read(buffer)?;
+        if read == 0 && !buffer.is_empty() {
+            self.eof = true;
+        }
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::Error;
+    use signature::Signature;
+
+    #[test]
+    fn empty_stream_has_no_frames()
+    {
+        let mut reader = FrameReader::new(io::Cursor::new(Vec::new()));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn concatenated_frames_are_read_in_order()
+    {
+        let (stream, _) = encode(&[test_frame(1), test_frame(2), test_frame(3)]);
+
+        let messages: Vec<Vec<u8>> = FrameReader::new(io::Cursor::new(stream))
+            .map(|frame| frame.unwrap().message)
+            .collect();
+        assert_eq!(messages, vec![vec![1u8; 1], vec![2u8; 2], vec![3u8; 3]]);
+    }
+
+    #[test]
+    fn offset_follows_the_frame_boundaries()
+    {
+        let (stream, offsets) = encode(&[test_frame(1), test_frame(2)]);
+        let length = stream.len() as u64;
+        let mut reader = FrameReader::new(io::Cursor::new(stream));
+
+        assert_eq!(reader.offset(), 0);
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.offset(), offsets[1]);
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.offset(), length);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn partial_trailing_frame_is_reported_as_truncated()
+    {
+        let (mut stream, offsets) = encode(&[test_frame(1), test_frame(2)]);
+        stream.pop();
+
+        let mut reader = FrameReader::new(io::Cursor::new(stream));
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(Error(ErrorKind::TruncatedFrame(offset), _))) => assert_eq!(offset, offsets[1]),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn corrupt_frame_is_reported_with_its_offset()
+    {
+        let (mut stream, offsets) = encode(&[test_frame(1), test_frame(2), test_frame(3)]);
+        // version of the second frame
+        stream[offsets[1] as usize + 1] = 9;
+
+        let mut reader = FrameReader::new(io::Cursor::new(stream));
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(Error(ErrorKind::CorruptFrame(offset), _))) => assert_eq!(offset, offsets[1]),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn non_canonical_frame_is_rejected_by_a_strict_reader()
+    {
+        let (stream, _) = encode(&[test_frame(1)]);
+        // version 1 as uint8
+        let mut non_canonical = vec![stream[0], 0xcc];
+        non_canonical.extend_from_slice(&stream[1..]);
+
+        let mut lenient = FrameReader::new(io::Cursor::new(non_canonical.clone()));
+        assert!(lenient.next().unwrap().is_ok());
+
+        let mut strict = FrameReader::with_options(io::Cursor::new(non_canonical), DecodeOptions::strict());
+        match strict.next() {
+            Some(Err(Error(ErrorKind::CorruptFrame(0), _))) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    fn encode(frames: &[Frame]) -> (Vec<u8>, Vec<u64>)
+    {
+        let mut stream = Vec::new();
+        let mut offsets = Vec::new();
+        for frame in frames {
+            offsets.push(stream.len() as u64);
+            frame.write(&mut stream).unwrap();
+        }
+        (stream, offsets)
+    }
+
+    fn test_frame(n: u8) -> Frame
+    {
+        Frame {
+            version: 1,
+            message: vec![n; n as usize],
+            signature: Signature([n; 64]),
+        }
+    }
+}
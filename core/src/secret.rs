@@ -0,0 +1,104 @@
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{self, Ordering};
+
+use ring;
+
+use errors::{Result, ResultExt};
+use KeyPair;
+
+/// A PKCS#8 encoded Ed25519 private key
+///
+/// The bytes are overwritten with zeros when the key is dropped and they are
+/// never printed by `Debug`. The key is not `Clone`, so the bytes exist only
+/// once in memory; pass it by reference.
+pub struct SecretKey(Vec<u8>);
+
+impl SecretKey {
+    /// Generates a new key from the system random source
+    pub fn generate() -> Result<SecretKey>
+    {
+        let randgen = ring::rand::SystemRandom::new();
+        let mut key_bytes = ring::signature::Ed25519KeyPair::generate_pkcs8(&randgen)
+            .chain_err(|| "Could not generate Ed25519 key!")?;
+        let secret = SecretKey(key_bytes.to_vec());
+        zeroize(&mut key_bytes);
+        Ok(secret)
+    }
+
+    /// Takes ownership of the encoded key, e.g. as read from a key file
+    pub fn from_bytes(bytes: Vec<u8>) -> SecretKey
+    {
+        SecretKey(bytes)
+    }
+
+    /// The PKCS#8 encoded key, to be written to a file readable only by its owner
+    pub fn as_bytes(&self) -> &[u8]
+    {
+        &self.0
+    }
+
+    /// Loads the keypair, see [`load_key`]
+    ///
+    /// [`load_key`]: ../fn.load_key.html
+    pub fn keypair(&self) -> Result<KeyPair>
+    {
+        ::load_key(&self.0)
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self)
+    {
+        zeroize(&mut self.0);
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretKey(<redacted>)")
+    }
+}
+
+/// Overwrites the bytes with zeros, the writes are not optimized away
+fn zeroize(bytes: &mut [u8])
+{
+    for byte in bytes.iter_mut() {
+        // a volatile write is kept even if the memory is never read again
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::PubKey;
+
+    #[test]
+    fn generated_key_can_be_loaded_as_keypair()
+    {
+        let secret = SecretKey::generate().unwrap();
+        let keypair = secret.keypair().unwrap();
+
+        let reloaded = SecretKey::from_bytes(secret.as_bytes().to_vec());
+        assert_eq!(PubKey::from_keypair(&reloaded.keypair().unwrap()), PubKey::from_keypair(&keypair));
+    }
+
+    #[test]
+    fn debug_does_not_show_the_key()
+    {
+        let secret = SecretKey::from_bytes(vec![0xab; 85]);
+        let debug = format!("{:?}", secret);
+        assert_eq!(debug, "SecretKey(<redacted>)");
+    }
+
+    #[test]
+    fn zeroize_clears_every_byte()
+    {
+        let mut bytes = [0xffu8; 85];
+        zeroize(&mut bytes);
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+}
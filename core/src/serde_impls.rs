@@ -0,0 +1,267 @@
+//! `Serialize` and `Deserialize` of the byte types, enabled by the `serde` feature
+//!
+//! The structs and enums derive them, this module encodes their byte fields.
+//! Byte fields are strings in human-readable formats (e.g. JSON), and raw bytes
+//! in binary formats:
+//!
+//! * [`PubKey`]: the canonical `@<base64>.ed25519` form,
+//! * [`Hash`] and [`MessageId`]: hex,
+//! * [`Signature`], content types and contents: base64.
+//!
+//! This is independent of the signed msgpack wire format, see [`Message::write`].
+//!
+//! [`PubKey`]: ../message/struct.PubKey.html
+//! [`Hash`]: ../message/struct.Hash.html
+//! [`MessageId`]: ../message/struct.MessageId.html
+//! [`Signature`]: ../signature/struct.Signature.html
+//! [`Message::write`]: ../message/struct.Message.html#method.write
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64;
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use message::{ContentType, Hash, MessageId, PubKey};
+use signature::Signature;
+
+/// Serializes a byte string as base64 in human-readable formats
+struct Base64<'a>(&'a [u8]);
+
+impl<'a> Serialize for Base64<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::encode(self.0))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+/// Deserializes a byte string serialized by `Base64`
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D>(deserializer: D) -> Result<ByteBuf, D::Error>
+        where D: Deserializer<'de>
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(ByteBufVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(ByteBufVisitor)
+        }
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str("a base64 string or a byte array")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<ByteBuf, E>
+        where E: de::Error
+    {
+        base64::decode(value)
+            .map(ByteBuf)
+            .map_err(|_| E::invalid_value(Unexpected::Str(value), &self))
+    }
+
+    fn visit_bytes<E>(self, value: &[u8]) -> Result<ByteBuf, E>
+        where E: de::Error
+    {
+        Ok(ByteBuf(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<ByteBuf, E>
+        where E: de::Error
+    {
+        Ok(ByteBuf(value))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<ByteBuf, A::Error>
+        where A: SeqAccess<'de>
+    {
+        let mut bytes = Vec::new();
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(ByteBuf(bytes))
+    }
+}
+
+/// Implements serde for a fixed size byte type with a text form:
+/// the text form in human-readable formats, the raw bytes otherwise
+macro_rules! serde_text_or_bytes {
+    ($ty:ident, $from_slice:expr) => {
+        impl Serialize for $ty {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where S: Serializer
+            {
+                if serializer.is_human_readable() {
+                    serializer.collect_str(self)
+                } else {
+                    serializer.serialize_bytes(&self.0[..])
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D>(deserializer: D) -> Result<$ty, D::Error>
+                where D: Deserializer<'de>
+            {
+                if deserializer.is_human_readable() {
+                    let text = String::deserialize(deserializer)?;
+                    $ty::from_str(&text).map_err(de::Error::custom)
+                } else {
+                    let bytes = ByteBuf::deserialize(deserializer)?;
+                    $from_slice(&bytes.0).map_err(de::Error::custom)
+                }
+            }
+        }
+    }
+}
+
+serde_text_or_bytes!(PubKey, PubKey::from_slice);
+serde_text_or_bytes!(Hash, Hash::from_slice);
+serde_text_or_bytes!(MessageId, |bytes| Hash::from_slice(bytes).map(MessageId::from));
+
+/// The encoding of byte string fields, for `#[serde(with = "::serde_impls::base64_bytes")]`
+pub mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::{Base64, ByteBuf};
+
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+        where T: AsRef<[u8]>, S: Serializer
+    {
+        Base64(bytes.as_ref()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+        where D: Deserializer<'de>
+    {
+        Ok(ByteBuf::deserialize(deserializer)?.0)
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        Base64(&self.0[..]).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D>(deserializer: D) -> Result<Signature, D::Error>
+        where D: Deserializer<'de>
+    {
+        let bytes = ByteBuf::deserialize(deserializer)?;
+        Signature::new(&bytes.0).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for ContentType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        Base64(self.as_bytes()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentType {
+    fn deserialize<D>(deserializer: D) -> Result<ContentType, D::Error>
+        where D: Deserializer<'de>
+    {
+        Ok(ContentType::from_bytes(ByteBuf::deserialize(deserializer)?.0))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use compression::Compression;
+    use frame::Frame;
+    use message::{Message, Stamp};
+
+    #[test]
+    fn byte_types_are_strings_in_human_readable_formats()
+    {
+        let pubkey = PubKey([1u8; 32]);
+        assert_eq!(serde_json::to_string(&pubkey).unwrap(), format!("\"{}\"", pubkey));
+
+        let hash = Hash([0xabu8; 64]);
+        assert_eq!(serde_json::to_string(&hash).unwrap(), format!("\"{}\"", "ab".repeat(64)));
+
+        let signature = Signature([0u8; 64]);
+        assert_eq!(serde_json::to_string(&signature).unwrap(), format!("\"{}\"", base64::encode(&[0u8; 64][..])));
+    }
+
+    #[test]
+    fn message_round_trips_through_json()
+    {
+        let message = Message {
+            author: PubKey([1u8; 32]),
+            parent: Some(Hash([2u8; 64])),
+            stamp: Some(Stamp { sequence: 3, timestamp: 1_500_000_000_000 }),
+            compression: Compression::Deflate,
+            content_type: ContentType::Custom(b"post".to_vec()),
+            content: b"hello".to_vec(),
+        };
+
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["content"], "aGVsbG8=");
+        assert_eq!(json["stamp"]["sequence"], 3);
+        assert_eq!(json["compression"], "deflate");
+
+        let decoded: Message = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(decoded.author, message.author);
+        assert_eq!(decoded.parent, message.parent);
+        assert_eq!(decoded.stamp, message.stamp);
+        assert_eq!(decoded.compression, message.compression);
+        assert_eq!(decoded.content_type, message.content_type);
+        assert_eq!(decoded.content, message.content);
+        assert_eq!(decoded.id().unwrap(), message.id().unwrap());
+
+        let mut uncompressed = json;
+        uncompressed.as_object_mut().unwrap().remove("compression");
+        let decoded: Message = serde_json::from_value(uncompressed).unwrap();
+        assert_eq!(decoded.compression, Compression::None);
+    }
+
+    #[test]
+    fn frame_round_trips_through_json()
+    {
+        let frame = Frame {
+            version: 1,
+            message: vec![1u8, 2u8, 3u8],
+            signature: Signature([4u8; 64]),
+        };
+
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(json, serde_json::to_string(&frame.view()).unwrap());
+
+        let decoded: Frame = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.version, frame.version);
+        assert_eq!(decoded.message, frame.message);
+        assert_eq!(decoded.signature, frame.signature);
+    }
+
+    #[test]
+    fn malformed_fields_are_rejected()
+    {
+        assert!(serde_json::from_str::<PubKey>("\"@AAAA.ed25519\"").is_err());
+        assert!(serde_json::from_str::<Signature>("\"not base64\"").is_err());
+        assert!(serde_json::from_str::<Frame>(r#"{"version": 1, "message": ""}"#).is_err());
+        assert!(serde_json::from_str::<Stamp>(r#"{"sequence": 1, "timestamp": 2, "other": 3}"#).is_err());
+    }
+}
@@ -1,16 +1,18 @@
 
 use std::fmt;
-use ::errors::Result;
+use ring;
+use ::errors::{ErrorKind, Result};
 
 /// An Ed25519 signature
 ///
 /// Size of Ed25519 signature is 64 bytes (twice of the public key)
+#[derive(Clone, Copy)]
 pub struct Signature(pub [u8; 64]);
 
 impl Signature {
     pub fn new(slice: &[u8]) -> Result<Signature> {
         if slice.len() != 64 {
-            bail!("Signature bytes length should be 64, but it is {}", slice.len())
+            bail!(ErrorKind::BadSignatureLength(slice.len() as u32))
         } else {
             let mut bytes = [0u8; 64];
             bytes.copy_from_slice(slice);
@@ -28,15 +30,13 @@ impl fmt::Debug for Signature {
     }
 }
 
+/// Compares in constant time, so the time taken does not tell how many
+/// leading bytes match
 impl PartialEq for Signature {
     fn eq(&self, rhs: &Signature) -> bool {
-        for (x1, x2) in self.0.iter().zip(rhs.0.iter())
-        {
-            if x1 != x2 {
-                return false;
-            }
-        }
-        true
+        ring::constant_time::verify_slices_are_equal(&self.0, &rhs.0).is_ok()
     }
 }
 
+impl Eq for Signature {}
+
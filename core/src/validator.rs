@@ -0,0 +1,376 @@
+use std::fmt;
+
+use errors::{ErrorKind, Result, ResultExt};
+use frame::Frame;
+use message::{Hash, Message, MessageId, PubKey};
+use options::DecodeOptions;
+
+/// The ways a feed can be broken, see [`FeedValidator`]
+///
+/// [`FeedValidator`]: struct.FeedValidator.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainViolation {
+    /// The message could not be decoded from the frame
+    UndecodableMessage,
+    /// The signature does not match the author of the message
+    BadSignature,
+    /// The author differs from the author of the previous messages
+    ForkedAuthor,
+    /// The first message of the feed has a parent
+    RootWithParent,
+    /// A message other than the first one has no parent
+    SecondRoot,
+    /// The parent is not the hash of the previous message
+    WrongParent,
+    /// The sequence number of the message differs from its position
+    WrongSequence,
+    /// The message has no stamp, but an earlier message of the feed has one
+    MissingStamp,
+}
+
+impl fmt::Display for ChainViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            ChainViolation::UndecodableMessage => "message can not be decoded",
+            ChainViolation::BadSignature => "bad signature",
+            ChainViolation::ForkedAuthor => "author differs from the author of the feed",
+            ChainViolation::RootWithParent => "first message has a parent",
+            ChainViolation::SecondRoot => "message has no parent, but it is not the first one",
+            ChainViolation::WrongParent => "parent is not the hash of the previous message",
+            ChainViolation::WrongSequence => "sequence number differs from the position of the message",
+            ChainViolation::MissingStamp => "message has no stamp, but an earlier message has one",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Validates a feed by consuming its frames in order
+///
+/// A feed is valid if
+///
+/// * every message has the canonical encoding, see [`DecodeOptions`],
+/// * every frame is signed by the author of its message,
+/// * the author is the same for every message,
+/// * only the first message has no parent,
+/// * the parent of every other message is the [`Hash`] of the previous message's [`MessageId`],
+/// * the sequence number of every stamped (version 2 or 3) message is its position,
+/// * once a message is stamped, every later message is stamped too.
+///
+/// The first broken link is reported as [`ErrorKind::BrokenChain`] with its position
+/// (the zero-based index of the frame in the feed). A rejected frame does not change
+/// the state of the validator.
+///
+/// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+/// [`Hash`]: ../message/struct.Hash.html
+/// [`MessageId`]: ../message/struct.MessageId.html
+/// [`ErrorKind::BrokenChain`]: ../errors/enum.ErrorKind.html
+#[derive(Debug, Default)]
+pub struct FeedValidator {
+    author: Option<PubKey>,
+    previous: Option<Hash>,
+    position: u64,
+    stamped: bool,
+}
+
+impl FeedValidator {
+    /// Creates a validator expecting the first message of a feed
+    pub fn new() -> FeedValidator
+    {
+        FeedValidator::default()
+    }
+
+    /// Creates a validator continuing an already validated feed
+    ///
+    /// * `author`: the author of the feed
+    /// * `head`: the identifier of the latest message of the feed
+    /// * `length`: number of messages in the feed
+    /// * `stamped`: whether the latest message of the feed has a [`Stamp`]
+    ///
+    /// [`Stamp`]: ../message/struct.Stamp.html
+    pub fn resume(author: PubKey, head: MessageId, length: u64, stamped: bool) -> FeedValidator
+    {
+        FeedValidator {
+            author: Some(author),
+            previous: Some(head.into()),
+            position: length,
+            stamped,
+        }
+    }
+
+    /// Position of the next frame, i.e. the number of already accepted frames
+    pub fn position(&self) -> u64
+    {
+        self.position
+    }
+
+    /// Validates the next frame of the feed and returns its decoded message
+    pub fn validate(&mut self, frame: &Frame) -> Result<Message>
+    {
+        let position = self.position;
+        let message = frame.decode_message_with(&DecodeOptions::strict())
+            .chain_err(|| ErrorKind::BrokenChain(position, ChainViolation::UndecodableMessage))?;
+
+        if let Some(ref author) = self.author {
+            if *author != message.author {
+                bail!(ErrorKind::BrokenChain(position, ChainViolation::ForkedAuthor));
+            }
+        }
+
+        if !frame.verify(&message.author) {
+            bail!(ErrorKind::BrokenChain(position, ChainViolation::BadSignature));
+        }
+
+        match (self.previous.as_ref(), message.parent.as_ref()) {
+            (None, Some(_)) => bail!(ErrorKind::BrokenChain(position, ChainViolation::RootWithParent)),
+            (Some(_), None) => bail!(ErrorKind::BrokenChain(position, ChainViolation::SecondRoot)),
+            (Some(previous), Some(parent)) if previous != parent =>
+                bail!(ErrorKind::BrokenChain(position, ChainViolation::WrongParent)),
+            _ => {}
+        }
+
+        match message.stamp {
+            Some(ref stamp) if stamp.sequence != position =>
+                bail!(ErrorKind::BrokenChain(position, ChainViolation::WrongSequence)),
+            None if self.stamped => bail!(ErrorKind::BrokenChain(position, ChainViolation::MissingStamp)),
+            _ => {}
+        }
+
+        self.author = Some(message.author);
+        self.stamped = message.stamp.is_some();
+        self.previous = Some(frame.message_id().into());
+        self.position += 1;
+
+        Ok(message)
+    }
+
+    /// Validates all `frames` and returns the number of accepted frames
+    pub fn validate_all<I>(&mut self, frames: I) -> Result<u64>
+        where I: IntoIterator<Item = Frame>
+    {
+        let start = self.position;
+        for frame in frames {
+            self.validate(&frame)?;
+        }
+        Ok(self.position - start)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::load_key;
+    use ::generate_private_key;
+    use compression::Compression;
+    use errors::Error;
+    use message::{ContentType, Stamp};
+    use signature::Signature;
+    use ring::signature::Ed25519KeyPair;
+
+    #[test]
+    fn properly_chained_feed_is_valid()
+    {
+        let keypair = new_keypair();
+        let frames = create_feed(&keypair, 3);
+
+        let mut validator = FeedValidator::new();
+        assert_eq!(validator.validate_all(frames).unwrap(), 3);
+        assert_eq!(validator.position(), 3);
+    }
+
+    #[test]
+    fn empty_feed_is_valid()
+    {
+        assert_eq!(FeedValidator::new().validate_all(vec![]).unwrap(), 0);
+    }
+
+    #[test]
+    fn bad_signature_is_reported()
+    {
+        let keypair = new_keypair();
+        let mut frames = create_feed(&keypair, 3);
+        frames[1].signature = Signature([0u8; 64]);
+
+        assert_violation(frames, 1, ChainViolation::BadSignature);
+    }
+
+    #[test]
+    fn wrong_parent_is_reported()
+    {
+        let keypair = new_keypair();
+        let mut frames = create_feed(&keypair, 3);
+        let wrong_parent = frames[0].message_id().into();
+        frames[2] = create_frame(&keypair, Some(wrong_parent));
+
+        assert_violation(frames, 2, ChainViolation::WrongParent);
+    }
+
+    #[test]
+    fn second_root_is_reported()
+    {
+        let keypair = new_keypair();
+        let mut frames = create_feed(&keypair, 2);
+        frames.push(create_frame(&keypair, None));
+
+        assert_violation(frames, 2, ChainViolation::SecondRoot);
+    }
+
+    #[test]
+    fn root_with_parent_is_reported()
+    {
+        let keypair = new_keypair();
+        let frames = vec![create_frame(&keypair, Some(Hash([0u8; 64])))];
+
+        assert_violation(frames, 0, ChainViolation::RootWithParent);
+    }
+
+    #[test]
+    fn forked_author_is_reported()
+    {
+        let keypair = new_keypair();
+        let mut frames = create_feed(&keypair, 2);
+        let parent = frames[1].message_id().into();
+        frames.push(create_frame(&new_keypair(), Some(parent)));
+
+        assert_violation(frames, 2, ChainViolation::ForkedAuthor);
+    }
+
+    #[test]
+    fn undecodable_message_is_reported()
+    {
+        let frames = vec![Frame {
+            version: 1,
+            message: vec![0x94, 0xc4],
+            signature: Signature([0u8; 64]),
+        }];
+
+        assert_violation(frames, 0, ChainViolation::UndecodableMessage);
+    }
+
+    #[test]
+    fn non_canonical_message_is_reported()
+    {
+        let keypair = new_keypair();
+        let mut frames = create_feed(&keypair, 1);
+        frames[0].message.push(0x00);
+
+        assert_violation(frames, 0, ChainViolation::UndecodableMessage);
+    }
+
+    #[test]
+    fn rejected_frame_does_not_change_the_state()
+    {
+        let keypair = new_keypair();
+        let frames = create_feed(&keypair, 2);
+        let mut validator = FeedValidator::new();
+        validator.validate(&frames[0]).unwrap();
+
+        assert!(validator.validate(&create_frame(&keypair, None)).is_err());
+        assert_eq!(validator.position(), 1);
+        assert!(validator.validate(&frames[1]).is_ok());
+    }
+
+    #[test]
+    fn resumed_validator_continues_the_feed()
+    {
+        let keypair = new_keypair();
+        let frames = create_feed(&keypair, 3);
+        let author = PubKey::from_keypair(&keypair);
+
+        let mut validator = FeedValidator::resume(author, frames[1].message_id(), 2, false);
+        validator.validate(&frames[2]).unwrap();
+        assert_eq!(validator.position(), 3);
+    }
+
+    #[test]
+    fn unstamped_message_after_a_stamped_one_is_reported()
+    {
+        let keypair = new_keypair();
+        let root = create_stamped_frame(&keypair, None, 0);
+        let parent = root.message_id().into();
+        let unstamped = create_frame(&keypair, Some(parent));
+
+        let author = PubKey::from_keypair(&keypair);
+        let mut validator = FeedValidator::resume(author, root.message_id(), 1, true);
+        match validator.validate(&unstamped) {
+            Err(Error(ErrorKind::BrokenChain(1, ChainViolation::MissingStamp), _)) => {},
+            other => panic!("Expected missing stamp, got {:?}", other),
+        }
+
+        assert_violation(vec![root, unstamped], 1, ChainViolation::MissingStamp);
+    }
+
+    #[test]
+    fn stamped_feed_is_valid()
+    {
+        let keypair = new_keypair();
+        let root = create_stamped_frame(&keypair, None, 0);
+        let parent = root.message_id().into();
+        let frames = vec![root, create_stamped_frame(&keypair, Some(parent), 1)];
+
+        assert_eq!(FeedValidator::new().validate_all(frames).unwrap(), 2);
+    }
+
+    #[test]
+    fn wrong_sequence_is_reported()
+    {
+        let keypair = new_keypair();
+        let mut frames = create_feed(&keypair, 2);
+        let parent = frames[1].message_id().into();
+        frames.push(create_stamped_frame(&keypair, Some(parent), 5));
+
+        assert_violation(frames, 2, ChainViolation::WrongSequence);
+    }
+
+    fn assert_violation(frames: Vec<Frame>, expected_position: u64, expected: ChainViolation)
+    {
+        match FeedValidator::new().validate_all(frames) {
+            Err(Error(ErrorKind::BrokenChain(position, violation), _)) => {
+                assert_eq!(position, expected_position);
+                assert_eq!(violation, expected);
+            },
+            other => panic!("Expected broken chain, got {:?}", other),
+        }
+    }
+
+    fn new_keypair() -> Ed25519KeyPair
+    {
+        load_key(generate_private_key().unwrap().as_bytes()).unwrap()
+    }
+
+    fn create_feed(keypair: &Ed25519KeyPair, length: usize) -> Vec<Frame>
+    {
+        let mut frames: Vec<Frame> = Vec::new();
+        for _ in 0..length {
+            let parent = frames.last().map(|frame| frame.message_id().into());
+            frames.push(create_frame(keypair, parent));
+        }
+        frames
+    }
+
+    fn create_frame(keypair: &Ed25519KeyPair, parent: Option<Hash>) -> Frame
+    {
+        let message = Message {
+            author: PubKey::from_keypair(keypair),
+            parent,
+            stamp: None,
+            compression: Compression::None,
+            content_type: ContentType::Blob,
+            content: vec![42u8],
+        };
+        Frame::new_signed(&message, keypair).unwrap()
+    }
+
+    fn create_stamped_frame(keypair: &Ed25519KeyPair, parent: Option<Hash>, sequence: u64) -> Frame
+    {
+        let message = Message {
+            author: PubKey::from_keypair(keypair),
+            parent,
+            stamp: Some(Stamp { sequence, timestamp: 1_500_000_000_000 }),
+            compression: Compression::None,
+            content_type: ContentType::Blob,
+            content: vec![42u8],
+        };
+        Frame::new_signed(&message, keypair).unwrap()
+    }
+}
@@ -0,0 +1,485 @@
+//! Borrowed views of frames and messages
+//!
+//! [`FrameRef`] and [`MessageRef`] parse the headers in place and refer to
+//! the variable length parts of the input instead of copying them, so a feed
+//! can be scanned without allocating for every frame. They can be converted
+//! to the owned [`Frame`] and [`Message`] on demand.
+//!
+//! [`FrameRef`]: struct.FrameRef.html
+//! [`MessageRef`]: struct.MessageRef.html
+//! [`Frame`]: ../frame/struct.Frame.html
+//! [`Message`]: ../message/struct.Message.html
+
+use std::borrow::Cow;
+
+use errors::{ErrorKind, Result, ResultExt};
+use codec;
+use compression::Compression;
+use content::{self, ContentCodec};
+use frame::Frame;
+use layout;
+use message::{ContentType, Hash, Message, MessageId, PubKey, Stamp};
+use options::{DecodeLimits, DecodeOptions};
+use signature::Signature;
+use ring;
+
+/// A [`Frame`] borrowing its message from the input
+///
+/// [`Frame`]: ../frame/struct.Frame.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename = "Frame"))]
+pub struct FrameRef<'a> {
+    /// the layout version of the `Frame` and of the wrapped message, 1, 2 or 3
+    pub version: u32,
+
+    /// the serialized format of the message
+    #[cfg_attr(feature = "serde", serde(serialize_with = "::serde_impls::base64_bytes::serialize"))]
+    pub message: &'a [u8],
+
+    /// The Ed25519 signature of the `message` field.
+    pub signature: Signature,
+}
+
+impl<'a> FrameRef<'a> {
+    /// Reads a frame from the start of `bytes` and advances it past the frame
+    ///
+    /// The signature is not verified. Malformed input results an error, it never panics.
+    pub fn read(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<FrameRef<'a>>
+    {
+        codec::read_array_len_exact(bytes, 3, options)?;
+        let version = codec::read_uint(bytes, options)?;
+        if !(1..=3).contains(&version) {
+            bail!(ErrorKind::UnsupportedFrameVersion(version));
+        }
+
+        let message = codec::read_bin_ref(bytes, options, codec::Field::Message)?;
+
+        let signature_len = codec::read_bin_len(bytes, options)?;
+        if signature_len != 64 {
+            bail!(ErrorKind::BadSignatureLength(signature_len));
+        }
+        let signature = Signature::new(codec::take(bytes, 64)?)?;
+
+        Ok(FrameRef {
+            version: version as u32,
+            message,
+            signature,
+        })
+    }
+
+    /// Decodes a frame from a complete buffer
+    ///
+    /// In strict mode trailing bytes after the frame are rejected.
+    pub fn decode(bytes: &'a [u8], options: &DecodeOptions) -> Result<FrameRef<'a>>
+    {
+        let mut remaining = bytes;
+        let frame = FrameRef::read(&mut remaining, options)?;
+        codec::check_consumed(remaining, options)?;
+        Ok(frame)
+    }
+
+    /// Decodes the wrapped message, which must have the layout of the frame's version
+    ///
+    /// In strict mode the message must be canonical and fill the whole `message` field.
+    pub fn decode_message(&self, options: &DecodeOptions) -> Result<MessageRef<'a>>
+    {
+        let mut remaining = self.message;
+        let message = MessageRef::read_version(&mut remaining, self.version, options)?;
+        codec::check_consumed(remaining, options)?;
+        Ok(message)
+    }
+
+    pub fn verify(&self, pubkey: &PubKey) -> bool
+    {
+        use ::untrusted::Input;
+        let digest = ring::digest::digest(&ring::digest::SHA512, self.message);
+
+        ring::signature::verify(&ring::signature::ED25519,
+                                Input::from(&pubkey.0[..]),
+                                Input::from(digest.as_ref()),
+                                Input::from(&self.signature.0[..])).is_ok()
+    }
+
+    /// The identifier of the wrapped message
+    pub fn message_id(&self) -> MessageId
+    {
+        MessageId::of_bytes(self.message)
+    }
+
+    /// Copies the frame into an owned [`Frame`]
+    ///
+    /// [`Frame`]: ../frame/struct.Frame.html
+    pub fn to_frame(&self) -> Frame
+    {
+        Frame {
+            version: self.version,
+            message: self.message.to_vec(),
+            signature: self.signature,
+        }
+    }
+}
+
+/// A [`Message`] borrowing its content type and content from the input
+///
+/// The fixed size fields are copied, they do not allocate.
+///
+/// [`Message`]: ../message/struct.Message.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename = "Message"))]
+pub struct MessageRef<'a> {
+    /// The Ed25519 public key of the author
+    pub author: PubKey,
+
+    /// Hash of the parent message, `None` only for the root message
+    pub parent: Option<Hash>,
+
+    /// Sequence number and timestamp, `None` for version 1 messages
+    pub stamp: Option<Stamp>,
+
+    /// Compression of the `content`, not `None` only for version 3 messages
+    pub compression: Compression,
+
+    /// The encoded content type, a single zero byte means [`ContentType::Blob`]
+    ///
+    /// [`ContentType::Blob`]: ../message/enum.ContentType.html
+    #[cfg_attr(feature = "serde", serde(serialize_with = "::serde_impls::base64_bytes::serialize"))]
+    pub content_type: &'a [u8],
+
+    /// The actual payload of the message
+    #[cfg_attr(feature = "serde", serde(serialize_with = "::serde_impls::base64_bytes::serialize"))]
+    pub content: &'a [u8],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Reads a message of any supported version from the start of `bytes`
+    /// and advances it past the message
+    pub fn read(bytes: &mut &'a [u8], options: &DecodeOptions) -> Result<MessageRef<'a>>
+    {
+        let items = codec::read_array_len(bytes, options)?;
+        let version = layout::version_of_array_len(items)?;
+        MessageRef::read_body(bytes, version, options)
+    }
+
+    /// Reads a message that must have the given layout `version`
+    pub fn read_version(bytes: &mut &'a [u8], version: u32, options: &DecodeOptions) -> Result<MessageRef<'a>>
+    {
+        codec::read_array_len_exact(bytes, layout::array_len(version)?, options)?;
+        MessageRef::read_body(bytes, version, options)
+    }
+
+    /// Decodes a message from a complete buffer
+    ///
+    /// In strict mode trailing bytes after the message are rejected.
+    pub fn decode(bytes: &'a [u8], options: &DecodeOptions) -> Result<MessageRef<'a>>
+    {
+        let mut remaining = bytes;
+        let message = MessageRef::read(&mut remaining, options)?;
+        codec::check_consumed(remaining, options)?;
+        Ok(message)
+    }
+
+    fn read_body(bytes: &mut &'a [u8], version: u32, options: &DecodeOptions) -> Result<MessageRef<'a>>
+    {
+        let header = layout::read_header(bytes, version, options)?;
+        Ok(MessageRef {
+            author: header.author,
+            parent: header.parent,
+            stamp: header.stamp,
+            compression: header.compression,
+            content_type: codec::read_bin_ref(bytes, options, codec::Field::ContentType)?,
+            content: codec::read_bin_ref(bytes, options, codec::Field::Content)?,
+        })
+    }
+
+    /// Version of the message layout, see [`Message::version`]
+    ///
+    /// [`Message::version`]: ../message/struct.Message.html#method.version
+    pub fn version(&self) -> u32
+    {
+        layout::version(self.compression, self.stamp)
+    }
+
+    /// The content, decompressed if needed, see [`Message::decompressed_content`]
+    ///
+    /// An uncompressed content is borrowed from the input.
+    ///
+    /// [`Message::decompressed_content`]: ../message/struct.Message.html#method.decompressed_content
+    pub fn decompressed_content(&self, limits: &DecodeLimits) -> Result<Cow<'a, [u8]>>
+    {
+        self.compression.decompress(self.content, limits)
+    }
+
+    /// Decodes the content as `T`, see [`Message::decode_content`]
+    ///
+    /// [`Message::decode_content`]: ../message/struct.Message.html#method.decode_content
+    pub fn decode_content<T>(&self) -> Result<T>
+        where T: ContentCodec
+    {
+        let content = self.decompressed_content(&DecodeLimits::default())?;
+        content::decode(self.content_type, &content)
+    }
+
+    /// Copies the message into an owned [`Message`]
+    ///
+    /// [`Message`]: ../message/struct.Message.html
+    pub fn to_message(&self) -> Message
+    {
+        Message {
+            author: self.author,
+            parent: self.parent,
+            stamp: self.stamp,
+            compression: self.compression,
+            content_type: ContentType::from_bytes(self.content_type.to_vec()),
+            content: self.content.to_vec(),
+        }
+    }
+}
+
+/// Iterates over the concatenated frames of a byte slice, e.g. a memory-mapped feed log
+///
+/// Like the [`FrameReader`], it ends at the end of the slice, and reports
+/// partial trailing data as [`ErrorKind::TruncatedFrame`] and any other undecodable
+/// data as [`ErrorKind::CorruptFrame`], with the offset where the frame starts.
+/// After an error the iterator ends.
+///
+/// [`FrameReader`]: ../reader/struct.FrameReader.html
+/// [`ErrorKind::TruncatedFrame`]: ../errors/enum.ErrorKind.html
+/// [`ErrorKind::CorruptFrame`]: ../errors/enum.ErrorKind.html
+#[derive(Debug, Clone)]
+pub struct FrameRefs<'a> {
+    remaining: &'a [u8],
+    offset: u64,
+    options: DecodeOptions,
+    failed: bool,
+}
+
+impl<'a> FrameRefs<'a> {
+    pub fn new(bytes: &'a [u8]) -> FrameRefs<'a>
+    {
+        FrameRefs::with_options(bytes, DecodeOptions::default())
+    }
+
+    /// Creates an iterator decoding the frames with the given [`DecodeOptions`]
+    ///
+    /// [`DecodeOptions`]: ../options/struct.DecodeOptions.html
+    pub fn with_options(bytes: &'a [u8], options: DecodeOptions) -> FrameRefs<'a>
+    {
+        FrameRefs {
+            remaining: bytes,
+            offset: 0,
+            options,
+            failed: false,
+        }
+    }
+
+    /// Number of bytes consumed from the slice so far
+    pub fn offset(&self) -> u64
+    {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for FrameRefs<'a> {
+    type Item = Result<FrameRef<'a>>;
+
+    fn next(&mut self) -> Option<Result<FrameRef<'a>>>
+    {
+        if self.failed || self.remaining.is_empty() {
+            return None;
+        }
+
+        let start = self.offset;
+        let mut remaining = self.remaining;
+        match FrameRef::read(&mut remaining, &self.options) {
+            Ok(frame) => {
+                self.offset += (self.remaining.len() - remaining.len()) as u64;
+                self.remaining = remaining;
+                Some(Ok(frame))
+            },
+            Err(e) => {
+                self.failed = true;
+                let kind = if is_truncated(&e) {
+                    ErrorKind::TruncatedFrame(start)
+                } else {
+                    ErrorKind::CorruptFrame(start)
+                };
+                Some(Err(e).chain_err(|| kind))
+            },
+        }
+    }
+}
+
+/// Tells if decoding failed because the input ended
+fn is_truncated(error: &::errors::Error) -> bool
+{
+    use std::io;
+    use rmp::decode::{NumValueReadError, ValueReadError};
+
+    let io_error = match *error.kind() {
+        ErrorKind::Io(ref e) => Some(e),
+        ErrorKind::ValueReadError(ValueReadError::InvalidMarkerRead(ref e)) |
+        ErrorKind::ValueReadError(ValueReadError::InvalidDataRead(ref e)) |
+        ErrorKind::NumValueReadError(NumValueReadError::InvalidMarkerRead(ref e)) |
+        ErrorKind::NumValueReadError(NumValueReadError::InvalidDataRead(ref e)) => Some(e),
+        _ => None,
+    };
+    match io_error {
+        Some(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+        None => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::Error;
+    use ::load_key;
+    use ::generate_private_key;
+
+    #[test]
+    fn frame_ref_borrows_the_message_of_the_input()
+    {
+        let (buffer, frame) = encoded_frame(test_message());
+        let frame_ref = FrameRef::decode(&buffer, &DecodeOptions::strict()).unwrap();
+
+        assert_eq!(frame_ref.version, frame.version);
+        assert_eq!(frame_ref.message, &frame.message[..]);
+        assert_eq!(frame_ref.signature, frame.signature);
+        // the message is a slice of the input, not a copy
+        let start = frame_ref.message.as_ptr() as usize - buffer.as_ptr() as usize;
+        assert_eq!(&buffer[start..start + frame.message.len()], frame_ref.message);
+    }
+
+    #[test]
+    fn frame_ref_verifies_like_the_owned_frame()
+    {
+        let keypair = load_key(generate_private_key().unwrap().as_bytes()).unwrap();
+        let mut message = test_message();
+        message.author = PubKey::from_keypair(&keypair);
+        let frame = Frame::new_signed(&message, &keypair).unwrap();
+
+        let frame_ref = frame.view();
+        assert!(frame_ref.verify(&message.author));
+        assert!(!frame_ref.verify(&PubKey([0u8; 32])));
+        assert_eq!(frame_ref.message_id(), frame.message_id());
+    }
+
+    #[test]
+    fn message_ref_converts_to_the_same_owned_message()
+    {
+        for message in &[test_message(), blob_message()] {
+            let mut buffer = Vec::new();
+            message.write(&mut buffer).unwrap();
+
+            let message_ref = MessageRef::decode(&buffer, &DecodeOptions::strict()).unwrap();
+            assert_eq!(message_ref.version(), message.version());
+            assert_eq!(message_ref.content, &message.content[..]);
+
+            let owned = message_ref.to_message();
+            assert_eq!(owned.author, message.author);
+            assert_eq!(owned.parent, message.parent);
+            assert_eq!(owned.stamp, message.stamp);
+            assert_eq!(owned.content_type, message.content_type);
+            assert_eq!(owned.content, message.content);
+        }
+    }
+
+    #[test]
+    fn message_layout_must_match_the_frame_version()
+    {
+        let (_, mut frame) = encoded_frame(test_message());
+        assert!(frame.view().decode_message(&DecodeOptions::strict()).is_ok());
+
+        frame.version = 1;
+        assert!(frame.view().decode_message(&DecodeOptions::strict()).is_err());
+    }
+
+    #[test]
+    fn concatenated_frames_are_iterated_in_order()
+    {
+        let (first, _) = encoded_frame(test_message());
+        let (second, _) = encoded_frame(blob_message());
+        let mut stream = first.clone();
+        stream.extend_from_slice(&second);
+
+        let mut frames = FrameRefs::new(&stream);
+        let contents: Vec<&[u8]> = frames.by_ref()
+            .map(|frame| frame.unwrap().decode_message(&DecodeOptions::default()).unwrap().content)
+            .collect();
+        assert_eq!(contents, vec![&b"hello"[..], &[1u8, 2u8][..]]);
+        assert_eq!(frames.offset(), stream.len() as u64);
+    }
+
+    #[test]
+    fn partial_trailing_frame_is_reported_as_truncated()
+    {
+        let (mut stream, _) = encoded_frame(test_message());
+        let second_start = stream.len() as u64;
+        let (second, _) = encoded_frame(blob_message());
+        stream.extend_from_slice(&second[..second.len() - 1]);
+
+        let mut frames = FrameRefs::new(&stream);
+        assert!(frames.next().unwrap().is_ok());
+        match frames.next() {
+            Some(Err(Error(ErrorKind::TruncatedFrame(offset), _))) => assert_eq!(offset, second_start),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn corrupt_frame_is_reported_with_its_offset()
+    {
+        let (mut stream, _) = encoded_frame(test_message());
+        // version of the frame
+        stream[1] = 9;
+
+        let mut frames = FrameRefs::new(&stream);
+        match frames.next() {
+            Some(Err(Error(ErrorKind::CorruptFrame(0), _))) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(frames.next().is_none());
+    }
+
+    fn encoded_frame(message: Message) -> (Vec<u8>, Frame)
+    {
+        let mut message_buffer = Vec::new();
+        message.write(&mut message_buffer).unwrap();
+        let frame = Frame {
+            version: message.version(),
+            message: message_buffer,
+            signature: Signature([7u8; 64]),
+        };
+        let mut buffer = Vec::new();
+        frame.write(&mut buffer).unwrap();
+        (buffer, frame)
+    }
+
+    fn test_message() -> Message
+    {
+        Message {
+            author: PubKey([1u8; 32]),
+            parent: Some(Hash([2u8; 64])),
+            stamp: Some(Stamp { sequence: 1, timestamp: 1_500_000_000_000 }),
+            compression: Compression::None,
+            content_type: ContentType::Custom(b"post".to_vec()),
+            content: b"hello".to_vec(),
+        }
+    }
+
+    fn blob_message() -> Message
+    {
+        Message {
+            author: PubKey([1u8; 32]),
+            parent: None,
+            stamp: None,
+            compression: Compression::None,
+            content_type: ContentType::Blob,
+            content: vec![1u8, 2u8],
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use kutyus_core::content::blob::{BlobHasher, BlobRef};
+use kutyus_core::message::Hash;
+
+use ::errors::{ErrorKind, Result};
+
+/// Stores blobs under a common directory, keyed by their SHA-512 hash
+///
+/// A blob is stored in `<first 2 hex digits>/<other 126 hex digits>` of its hash,
+/// so no directory holds too many files. Blobs are immutable: adding a stored
+/// blob again only replaces a file that does not match it.
+///
+/// Retrieved blobs are checked against their [`BlobRef`], so a corrupted or
+/// replaced file is never returned as the blob.
+///
+/// [`BlobRef`]: ../../kutyus_core/content/blob/struct.BlobRef.html
+#[derive(Debug)]
+pub struct BlobStore {
+    path: PathBuf,
+}
+
+impl BlobStore {
+    /// Opens the store at `path` (usually `<storage>/blobs`), creating it if needed
+    pub fn open<P>(path: P) -> Result<BlobStore>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+        Ok(BlobStore { path: path.to_path_buf() })
+    }
+
+    /// Stores the bytes read from `reader` and returns their reference
+    ///
+    /// The blob is written to a temporary file first, which is moved in place
+    /// once the hash is known. A stored file that does not match the blob is
+    /// replaced.
+    pub fn add<R>(&self, reader: &mut R) -> Result<BlobRef>
+        where R: Read
+    {
+        let temporary_path = self.path.join(format!("incoming-{}.tmp", unique_suffix()));
+        let blob = {
+            let mut hasher = BlobHasher::new(File::create(&temporary_path)?);
+            let copied = io::copy(reader, &mut hasher);
+            let (file, blob) = hasher.finish();
+            if let Err(error) = copied.and_then(|_| file.sync_all()) {
+                fs::remove_file(&temporary_path)?;
+                return Err(error.into());
+            }
+            blob
+        };
+
+        let path = self.blob_path(&blob.hash);
+        if path.is_file() && stored_blob(&path, &blob)? == blob {
+            fs::remove_file(&temporary_path)?;
+        } else {
+            fs::create_dir_all(path.parent().expect("Blob path should have a parent directory"))?;
+            fs::rename(&temporary_path, &path)?;
+        }
+        Ok(blob)
+    }
+
+    /// Whether the blob is stored
+    pub fn has(&self, hash: &Hash) -> bool
+    {
+        self.blob_path(hash).is_file()
+    }
+
+    /// The reference of the stored blob, with the size of its file
+    pub fn reference(&self, hash: &Hash) -> Result<Option<BlobRef>>
+    {
+        match self.open_blob(hash)? {
+            Some(file) => Ok(Some(BlobRef { hash: *hash, size: file.metadata()?.len() })),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes the blob to `writer` while checking it against the reference
+    ///
+    /// Returns `false` if the blob is not stored. A stored blob not matching the
+    /// reference is reported as [`ErrorKind::CorruptBlob`] once it is read, when
+    /// part of it is already written: the caller must discard the output on error,
+    /// e.g. write to a temporary file and keep it only on success. [`get`] returns
+    /// only checked blobs.
+    ///
+    /// [`ErrorKind::CorruptBlob`]: ../errors/enum.ErrorKind.html
+    /// [`get`]: #method.get
+    pub fn copy_to<W>(&self, blob: &BlobRef, writer: &mut W) -> Result<bool>
+        where W: Write
+    {
+        let file = match self.open_blob(&blob.hash)? {
+            Some(file) => file,
+            None => return Ok(false),
+        };
+        let mut hasher = BlobHasher::new(writer);
+        // one byte more than the reference, so a longer file does not match
+        io::copy(&mut file.take(blob.size.saturating_add(1)), &mut hasher)?;
+        if hasher.finish().1 != *blob {
+            bail!(ErrorKind::CorruptBlob(blob.hash.to_string()));
+        }
+        Ok(true)
+    }
+
+    /// Reads the whole blob into memory, it is returned only if it matches the reference
+    ///
+    /// See [`copy_to`] about the errors.
+    ///
+    /// [`copy_to`]: #method.copy_to
+    pub fn get(&self, blob: &BlobRef) -> Result<Option<Vec<u8>>>
+    {
+        let mut bytes = Vec::new();
+        if self.copy_to(blob, &mut bytes)? {
+            Ok(Some(bytes))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Opens the file of the blob, `None` if it is not stored
+    fn open_blob(&self, hash: &Hash) -> Result<Option<File>>
+    {
+        match File::open(self.blob_path(hash)) {
+            Ok(file) => Ok(Some(file)),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn blob_path(&self, hash: &Hash) -> PathBuf
+    {
+        let hex = hash.to_string();
+        self.path.join(&hex[..2]).join(&hex[2..])
+    }
+}
+
+/// The reference of the blob stored at `path`, reading at most one byte more than `blob`
+fn stored_blob(path: &Path, blob: &BlobRef) -> Result<BlobRef>
+{
+    let mut hasher = BlobHasher::new(io::sink());
+    io::copy(&mut File::open(path)?.take(blob.size.saturating_add(1)), &mut hasher)?;
+    Ok(hasher.finish().1)
+}
+
+/// A suffix that differs between concurrent adds
+fn unique_suffix() -> String
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos()).unwrap_or(0);
+    format!("{}-{}-{}", ::std::process::id(), nanos, COUNTER.fetch_add(1, Ordering::SeqCst))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use errors::Error;
+
+    #[test]
+    fn added_blob_can_be_retrieved_by_its_reference()
+    {
+        let dir = TempDir::new("blobs").unwrap();
+        let store = BlobStore::open(dir.path()).unwrap();
+
+        let blob = store.add(&mut &b"attachment"[..]).unwrap();
+        assert_eq!(blob, BlobRef::of_bytes(b"attachment"));
+        assert!(store.has(&blob.hash));
+        assert_eq!(store.reference(&blob.hash).unwrap(), Some(blob));
+        assert_eq!(store.get(&blob).unwrap(), Some(b"attachment".to_vec()));
+
+        // adding it again keeps the single copy
+        assert_eq!(store.add(&mut &b"attachment"[..]).unwrap(), blob);
+        let files = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn missing_blob_is_not_found()
+    {
+        let dir = TempDir::new("blobs").unwrap();
+        let store = BlobStore::open(dir.path()).unwrap();
+        let blob = BlobRef::of_bytes(b"missing");
+        assert!(!store.has(&blob.hash));
+        assert_eq!(store.reference(&blob.hash).unwrap(), None);
+        assert_eq!(store.get(&blob).unwrap(), None);
+    }
+
+    #[test]
+    fn corrupt_blob_is_not_returned()
+    {
+        let dir = TempDir::new("blobs").unwrap();
+        let store = BlobStore::open(dir.path()).unwrap();
+        let blob = store.add(&mut &b"attachment"[..]).unwrap();
+        fs::write(store.blob_path(&blob.hash), b"attachmenT").unwrap();
+
+        match store.get(&blob) {
+            Err(Error(ErrorKind::CorruptBlob(_), _)) => {},
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let mut output = Vec::new();
+        assert!(store.copy_to(&blob, &mut output).is_err());
+
+        // a reference with another size does not match either
+        fs::write(store.blob_path(&blob.hash), b"attachment").unwrap();
+        assert!(store.get(&BlobRef { size: 11, ..blob }).is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn corrupt_blob_is_replaced_when_added_again()
+    {
+        let dir = TempDir::new("blobs").unwrap();
+        let store = BlobStore::open(dir.path()).unwrap();
+        let blob = store.add(&mut &b"attachment"[..]).unwrap();
+        fs::write(store.blob_path(&blob.hash), b"attachmenT").unwrap();
+
+        assert_eq!(store.add(&mut &b"attachment"[..]).unwrap(), blob);
+        assert_eq!(store.get(&blob).unwrap(), Some(b"attachment".to_vec()));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use kutyus_core::frame::Frame;
+
+use ::errors::Result;
+
+/// Size of one entry of the offset index
+const INDEX_ENTRY_SIZE: u64 = 8;
+
+const LOG_FILE_NAME: &str = "feed.log";
+const INDEX_FILE_NAME: &str = "feed.idx";
+
+/// An append-only sequence of [`Frame`]s stored in a directory
+///
+/// The directory contains two files:
+///
+/// * `feed.log`: the serialized frames, concatenated
+/// * `feed.idx`: the byte offset of every frame in the log,
+///   as 8 bytes big-endian unsigned integers
+///
+/// The index is written after the frame itself, so it is the source of truth:
+/// a frame that has no index entry (e.g. because of a crash) is not part of the feed.
+///
+/// Sequence numbers start from zero.
+///
+/// [`Frame`]: ../../kutyus_core/frame/struct.Frame.html
+#[derive(Debug)]
+pub struct Feed {
+    log_path: PathBuf,
+    index_path: PathBuf,
+}
+
+impl Feed {
+    /// Opens the feed stored in `directory`, creating the directory if needed
+    pub fn open<P>(directory: P) -> Result<Feed>
+        where P: AsRef<Path>
+    {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+        Ok(Feed {
+            log_path: directory.join(LOG_FILE_NAME),
+            index_path: directory.join(INDEX_FILE_NAME),
+        })
+    }
+
+    /// Number of frames in the feed
+    pub fn len(&self) -> Result<u64>
+    {
+        if !self.index_path.exists() {
+            return Ok(0);
+        }
+        let index_size = fs::metadata(&self.index_path)?.len();
+        Ok(index_size / INDEX_ENTRY_SIZE)
+    }
+
+    pub fn is_empty(&self) -> Result<bool>
+    {
+        Ok(self.len()? == 0)
+    }
+
+    /// Appends the `frame` to the end of the feed and returns its sequence number
+    ///
+    /// The frame is not validated against the previous ones.
+    pub fn append(&self, frame: &Frame) -> Result<u64>
+    {
+        let sequence = self.len()?;
+
+        let mut buffer: Vec<u8> = Vec::with_capacity(frame.encoded_len());
+        frame.write(&mut buffer)?;
+
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)?;
+        let offset = log.metadata()?.len();
+        log.write_all(&buffer)?;
+        log.sync_data()?;
+
+        let mut index = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.index_path)?;
+        // drop a possibly half-written entry
+        index.set_len(sequence * INDEX_ENTRY_SIZE)?;
+        index.seek(SeekFrom::End(0))?;
+        index.write_all(&offset.to_be_bytes())?;
+        index.sync_data()?;
+
+        Ok(sequence)
+    }
+
+    /// Reads the frame with the given sequence number, `None` if there is no such frame
+    pub fn get(&self, sequence: u64) -> Result<Option<Frame>>
+    {
+        if sequence >= self.len()? {
+            return Ok(None);
+        }
+
+        let offset = self.read_offset(sequence)?;
+        let mut log = File::open(&self.log_path)?;
+        log.seek(SeekFrom::Start(offset))?;
+        let frame = Frame::read(&mut BufReader::new(log))?;
+        Ok(Some(frame))
+    }
+
+    /// Reads the latest frame of the feed, `None` if the feed is empty
+    pub fn head(&self) -> Result<Option<Frame>>
+    {
+        match self.len()? {
+            0 => Ok(None),
+            len => self.get(len - 1),
+        }
+    }
+
+    /// Iterates over all frames of the feed in order
+    pub fn frames(&self) -> Result<Frames>
+    {
+        let remaining = self.len()?;
+        let reader = if remaining == 0 {
+            None
+        } else {
+            Some(BufReader::new(File::open(&self.log_path)?))
+        };
+        Ok(Frames {
+            reader,
+            offsets: Offsets::new(&self.index_path, remaining)?,
+        })
+    }
+
+    fn read_offset(&self, sequence: u64) -> Result<u64>
+    {
+        let mut index = File::open(&self.index_path)?;
+        index.seek(SeekFrom::Start(sequence * INDEX_ENTRY_SIZE))?;
+        read_index_entry(&mut index)
+    }
+}
+
+/// Iterator over the frames of a [`Feed`]
+///
+/// [`Feed`]: struct.Feed.html
+pub struct Frames {
+    reader: Option<BufReader<File>>,
+    offsets: Offsets,
+}
+
+impl Iterator for Frames {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Result<Frame>>
+    {
+        let offset = match self.offsets.next() {
+            Some(Ok(offset)) => offset,
+            Some(Err(e)) => return Some(Err(e)),
+            None => return None,
+        };
+        let reader = self.reader.as_mut().expect("unreachable");
+        Some(read_frame_at(reader, offset))
+    }
+}
+
+fn read_frame_at(reader: &mut BufReader<File>, offset: u64) -> Result<Frame>
+{
+    reader.seek(SeekFrom::Start(offset))?;
+    Ok(Frame::read(reader)?)
+}
+
+/// Reads the entries of the offset index sequentially
+struct Offsets {
+    index: Option<BufReader<File>>,
+    remaining: u64,
+}
+
+impl Offsets {
+    fn new(index_path: &Path, count: u64) -> io::Result<Offsets>
+    {
+        let index = if count == 0 {
+            None
+        } else {
+            Some(BufReader::new(File::open(index_path)?))
+        };
+        Ok(Offsets { index, remaining: count })
+    }
+}
+
+impl Iterator for Offsets {
+    type Item = Result<u64>;
+
+    fn next(&mut self) -> Option<Result<u64>>
+    {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let index = self.index.as_mut().expect("unreachable");
+        Some(read_index_entry(index))
+    }
+}
+
+fn read_index_entry<R>(index: &mut R) -> Result<u64>
+    where R: Read
+{
+    let mut entry = [0u8; 8];
+    index.read_exact(&mut entry)?;
+    Ok(u64::from_be_bytes(entry))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use kutyus_core::signature::Signature;
+
+    #[test]
+    fn new_feed_is_empty()
+    {
+        let dir = TempDir::new("feed").unwrap();
+        let feed = Feed::open(dir.path()).unwrap();
+        assert_eq!(feed.len().unwrap(), 0);
+        assert!(feed.head().unwrap().is_none());
+        assert!(feed.get(0).unwrap().is_none());
+        assert_eq!(feed.frames().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn appended_frames_can_be_read_back_by_sequence_number()
+    {
+        let dir = TempDir::new("feed").unwrap();
+        let feed = Feed::open(dir.path()).unwrap();
+
+        assert_eq!(feed.append(&test_frame(1)).unwrap(), 0);
+        assert_eq!(feed.append(&test_frame(2)).unwrap(), 1);
+        assert_eq!(feed.append(&test_frame(3)).unwrap(), 2);
+
+        assert_eq!(feed.len().unwrap(), 3);
+        assert_eq!(feed.get(1).unwrap().unwrap().message, vec![2u8; 2]);
+        assert_eq!(feed.head().unwrap().unwrap().message, vec![3u8; 3]);
+        assert!(feed.get(3).unwrap().is_none());
+    }
+
+    #[test]
+    fn frames_are_iterated_in_order()
+    {
+        let dir = TempDir::new("feed").unwrap();
+        let feed = Feed::open(dir.path()).unwrap();
+        for i in 1..5 {
+            feed.append(&test_frame(i)).unwrap();
+        }
+
+        let messages: Vec<Vec<u8>> = feed.frames().unwrap()
+            .map(|frame| frame.unwrap().message)
+            .collect();
+        assert_eq!(messages, vec![vec![1u8; 1], vec![2u8; 2], vec![3u8; 3], vec![4u8; 4]]);
+    }
+
+    #[test]
+    fn reopened_feed_keeps_its_frames()
+    {
+        let dir = TempDir::new("feed").unwrap();
+        Feed::open(dir.path()).unwrap().append(&test_frame(7)).unwrap();
+
+        let feed = Feed::open(dir.path()).unwrap();
+        assert_eq!(feed.len().unwrap(), 1);
+        assert_eq!(feed.get(0).unwrap().unwrap().signature, Signature([7u8; 64]));
+    }
+
+    #[test]
+    fn unindexed_trailing_bytes_are_ignored()
+    {
+        let dir = TempDir::new("feed").unwrap();
+        let feed = Feed::open(dir.path()).unwrap();
+        feed.append(&test_frame(1)).unwrap();
+
+        // simulate a crash between writing the log and the index
+        OpenOptions::new().append(true).open(dir.path().join(LOG_FILE_NAME)).unwrap()
+            .write_all(&[0x93, 0x01]).unwrap();
+
+        assert_eq!(feed.len().unwrap(), 1);
+        assert_eq!(feed.append(&test_frame(2)).unwrap(), 1);
+        assert_eq!(feed.get(1).unwrap().unwrap().message, vec![2u8; 2]);
+    }
+
+    fn test_frame(n: u8) -> Frame
+    {
+        Frame {
+            version: 1,
+            message: vec![n; n as usize],
+            signature: Signature([n; 64]),
+        }
+    }
+}
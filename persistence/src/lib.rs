@@ -0,0 +1,34 @@
+extern crate kutyus_core;
+
+#[macro_use]
+extern crate error_chain;
+
+#[cfg(test)]
+extern crate tempdir;
+
+pub mod errors {
+    error_chain!{
+        foreign_links {
+            Io(::std::io::Error);
+        }
+
+        links {
+            Core(::kutyus_core::errors::Error, ::kutyus_core::errors::ErrorKind);
+        }
+
+        errors {
+            CorruptBlob(hash: String) {
+                description("stored blob does not match its reference")
+                display("stored blob {} does not match its reference", hash)
+            }
+        }
+    }
+}
+
+pub mod blobs;
+pub mod feed;
+pub mod store;
+
+pub use blobs::BlobStore;
+pub use feed::Feed;
+pub use store::FeedStore;
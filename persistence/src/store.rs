@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use kutyus_core::frame::Frame;
+use kutyus_core::message::PubKey;
+
+use ::errors::Result;
+use ::feed::Feed;
+
+/// Stores the feeds of all known authors under a common directory
+///
+/// Every author has its own [`Feed`] in a subdirectory, named by the
+/// hex-encoded public key of the author.
+///
+/// Feeds can also be stored under a chosen name, see [`named`].
+///
+/// [`Feed`]: ../feed/struct.Feed.html
+/// [`named`]: #method.named
+#[derive(Debug)]
+pub struct FeedStore {
+    path: PathBuf,
+}
+
+impl FeedStore {
+    /// Opens the store at `path` (usually `<storage>/feeds`), creating it if needed
+    pub fn open<P>(path: P) -> Result<FeedStore>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+        Ok(FeedStore { path: path.to_path_buf() })
+    }
+
+    /// The feed of the given author
+    pub fn feed(&self, author: &PubKey) -> Result<Feed>
+    {
+        Feed::open(self.path.join(author_directory_name(author)))
+    }
+
+    /// Whether the given author has a feed directory, without creating it
+    pub fn has_feed(&self, author: &PubKey) -> Result<bool>
+    {
+        Ok(self.path.join(author_directory_name(author)).is_dir())
+    }
+
+    /// The feed stored under `name`
+    ///
+    /// The name must not look like a hex-encoded public key
+    /// and must not contain path separators.
+    pub fn named(&self, name: &str) -> Result<Feed>
+    {
+        check_feed_name(name)?;
+        Feed::open(self.path.join(name))
+    }
+
+    /// Whether a feed called `name` exists
+    pub fn has_named(&self, name: &str) -> Result<bool>
+    {
+        check_feed_name(name)?;
+        Ok(self.path.join(name).is_dir())
+    }
+
+    /// Lists the names of the named feeds in the store
+    pub fn names(&self) -> Result<Vec<String>>
+    {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Ok(name) = entry.file_name().into_string() {
+                if check_feed_name(&name).is_ok() {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Path of the directory of the feed stored under `name`
+    pub fn named_path(&self, name: &str) -> Result<PathBuf>
+    {
+        check_feed_name(name)?;
+        Ok(self.path.join(name))
+    }
+
+    /// Appends the `frame` to the feed of its author and returns its sequence number
+    pub fn append(&self, frame: &Frame) -> Result<u64>
+    {
+        let message = frame.decode_message()?;
+        self.feed(&message.author)?.append(frame)
+    }
+
+    /// Lists the authors having a feed in the store
+    pub fn authors(&self) -> Result<Vec<PubKey>>
+    {
+        let mut authors = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(author) = entry.file_name().to_str().and_then(parse_author_directory_name) {
+                authors.push(author);
+            }
+        }
+        Ok(authors)
+    }
+}
+
+fn check_feed_name(name: &str) -> Result<()>
+{
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        && parse_author_directory_name(name).is_none();
+    if !valid {
+        bail!("Invalid feed name: {:?}", name);
+    }
+    Ok(())
+}
+
+fn author_directory_name(author: &PubKey) -> String
+{
+    author.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_author_directory_name(name: &str) -> Option<PubKey>
+{
+    if name.len() != 64 || !name.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&name[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(PubKey(bytes))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use kutyus_core::compression::Compression;
+    use kutyus_core::message::{ContentType, Message};
+    use kutyus_core::signature::Signature;
+
+    #[test]
+    fn frames_are_stored_in_the_feed_of_their_author()
+    {
+        let dir = TempDir::new("store").unwrap();
+        let store = FeedStore::open(dir.path()).unwrap();
+
+        assert_eq!(store.append(&test_frame(1, 10)).unwrap(), 0);
+        assert_eq!(store.append(&test_frame(2, 20)).unwrap(), 0);
+        assert_eq!(store.append(&test_frame(1, 11)).unwrap(), 1);
+
+        assert_eq!(store.feed(&PubKey([1u8; 32])).unwrap().len().unwrap(), 2);
+        assert_eq!(store.feed(&PubKey([2u8; 32])).unwrap().len().unwrap(), 1);
+        assert!(store.feed(&PubKey([3u8; 32])).unwrap().is_empty().unwrap());
+    }
+
+    #[test]
+    fn authors_are_listed_from_directory_names()
+    {
+        let dir = TempDir::new("store").unwrap();
+        let store = FeedStore::open(dir.path()).unwrap();
+        store.append(&test_frame(1, 10)).unwrap();
+        store.append(&test_frame(0xab, 20)).unwrap();
+        fs::create_dir(dir.path().join("not-an-author")).unwrap();
+
+        let mut authors = store.authors().unwrap();
+        authors.sort_by_key(|author| author.0);
+        assert_eq!(authors, vec![PubKey([1u8; 32]), PubKey([0xab; 32])]);
+    }
+
+    #[test]
+    fn named_feeds_are_stored_apart_from_author_feeds()
+    {
+        let dir = TempDir::new("store").unwrap();
+        let store = FeedStore::open(dir.path()).unwrap();
+        store.append(&test_frame(1, 10)).unwrap();
+
+        let feed = store.named("bots").unwrap();
+        feed.append(&test_frame(1, 11)).unwrap();
+
+        assert!(store.has_named("bots").unwrap());
+        assert!(!store.has_named("people").unwrap());
+        assert_eq!(store.names().unwrap(), vec!["bots".to_string()]);
+        assert_eq!(store.authors().unwrap(), vec![PubKey([1u8; 32])]);
+        assert_eq!(store.feed(&PubKey([1u8; 32])).unwrap().len().unwrap(), 1);
+    }
+
+    #[test]
+    fn invalid_feed_names_are_rejected()
+    {
+        let dir = TempDir::new("store").unwrap();
+        let store = FeedStore::open(dir.path()).unwrap();
+        assert!(store.named("").is_err());
+        assert!(store.named("..").is_err());
+        assert!(store.named("a/b").is_err());
+        assert!(store.named(&author_directory_name(&PubKey([1u8; 32]))).is_err());
+    }
+
+    #[test]
+    fn author_directory_name_is_reversible()
+    {
+        let author = PubKey([0x0f; 32]);
+        let name = author_directory_name(&author);
+        assert_eq!(name.len(), 64);
+        assert_eq!(parse_author_directory_name(&name), Some(author));
+    }
+
+    fn test_frame(author: u8, content: u8) -> Frame
+    {
+        let message = Message {
+            author: PubKey([author; 32]),
+            parent: None,
+            stamp: None,
+            compression: Compression::None,
+            content_type: ContentType::Blob,
+            content: vec![content],
+        };
+        let mut buffer = Vec::new();
+        message.write(&mut buffer).unwrap();
+        Frame {
+            version: 1,
+            message: buffer,
+            signature: Signature([0u8; 64]),
+        }
+    }
+}
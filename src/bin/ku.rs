@@ -2,14 +2,22 @@ extern crate clap;
 extern crate config;
 extern crate kutyus;
 extern crate kutyus_core;
+extern crate kutyus_persistence;
 
 use clap::{Arg, App, SubCommand, ArgMatches};
 
 use std::env;
 use std::path::{PathBuf, Path};
 
+use kutyus_core::KeyPair;
+use kutyus_persistence::Feed;
+
 use kutyus::errors::Result;
-use kutyus::config::{init, load_config, get_storage_path};
+use kutyus::config::{init, load_config, get_storage_path, get_hops};
+use kutyus::keys;
+use kutyus::feeds;
+use kutyus::social;
+use kutyus::transfer;
 
 
 fn main()
@@ -20,7 +28,9 @@ fn main()
     let config_file_path = Path::new(config_file_path_str);
 
     if let Some(ref init_matches) = matches.subcommand_matches("init") {
-        with_nice_error_handling(|| init(config_file_path, init_matches.is_present("force")))
+        with_nice_error_handling(|| init(config_file_path,
+                                              init_matches.is_present("force"),
+                                              init_matches.is_present("wipe-storage")))
     } else {
         with_nice_error_handling(|| do_work(config_file_path_str, &matches))
     }
@@ -38,26 +48,295 @@ fn do_work(config_file_path: &str, matches: &ArgMatches) -> Result<()>
 {
     let settings = load_config(config_file_path)?;
 
+    if let Some(m) = matches.subcommand_matches("keygen") {
+        let storage_path_string = get_storage_path(&settings);
+        let storage_path = Path::new(storage_path_string.as_str());
+        create_storage_dir(storage_path)?;
+        let name = m.value_of("name").expect("unreachable");
+        let pubkey = keys::generate(storage_path, name, m.is_present("force"))?;
+        println!(">> Generated key {:?} to {:?}, public key:", name, keys::key_path(storage_path, name));
+        println!("{}", pubkey);
+    }
+
     if let Some(m) = matches.subcommand_matches("newfeed") {
         let storage_path_string = get_storage_path(&settings);
         prepare_storage_area_if_needed(&storage_path_string)?;
+        let storage_path = Path::new(storage_path_string.as_str());
+        let name = m.value_of("name").expect("unreachable");
+        let key_name = m.value_of("key").unwrap_or(name);
+        // the default key can not be bound, `create` refuses it
+        if key_name != keys::DEFAULT_KEY_NAME && !keys::key_path(storage_path, key_name).exists() {
+            let pubkey = keys::generate(storage_path, key_name, false)?;
+            println!(">> Generated key {:?} for the feed, public key: {}", key_name, pubkey);
+        }
+        feeds::create(storage_path, name, key_name)?;
+        println!(">> Created feed {:?} bound to key {:?}", name, key_name);
     }
 
     if let Some(m) = matches.subcommand_matches("append") {
         let storage_path_string = get_storage_path(&settings);
         prepare_storage_area_if_needed(&storage_path_string)?;
-        println!("append: storage: {:?}", storage_path_string);
-        // get current/latest frame
-        // create new frame
+        append(Path::new(storage_path_string.as_str()), m)?;
+    }
+
+    if let Some(m) = matches.subcommand_matches("about") {
+        let storage_path_string = get_storage_path(&settings);
+        prepare_storage_area_if_needed(&storage_path_string)?;
+        about(Path::new(storage_path_string.as_str()), m)?;
+    }
+
+    if let Some(m) = matches.subcommand_matches("blob") {
+        let storage_path_string = get_storage_path(&settings);
+        prepare_storage_area_if_needed(&storage_path_string)?;
+        blob(Path::new(storage_path_string.as_str()), m)?;
+    }
+
+    if let Some(m) = matches.subcommand_matches("export") {
+        let storage_path_string = get_storage_path(&settings);
+        prepare_storage_area_if_needed(&storage_path_string)?;
+        export_to_stdout(Path::new(storage_path_string.as_str()), m)?;
+    }
+
+    if let Some(m) = matches.subcommand_matches("import") {
+        let storage_path_string = get_storage_path(&settings);
+        prepare_storage_area_if_needed(&storage_path_string)?;
+        let format = m.value_of("format").expect("unreachable").parse()?;
+        let scope = if m.is_present("all") {
+            transfer::Scope::All
+        } else {
+            match m.value_of("hops") {
+                Some(hops) => transfer::Scope::Hops(hops.parse().map_err(|_| format!("Invalid hops {:?}", hops))?),
+                None => transfer::Scope::Hops(get_hops(&settings)?),
+            }
+        };
+        let stdin = std::io::stdin();
+        let imported = transfer::import(Path::new(storage_path_string.as_str()), format, scope, stdin.lock())?;
+        println!(">> Imported {} messages, skipped {} already stored, ignored {} out of scope",
+                 imported.appended, imported.skipped, imported.ignored);
+    }
+
+    if let Some(m) = matches.subcommand_matches("contact") {
+        let storage_path_string = get_storage_path(&settings);
+        prepare_storage_area_if_needed(&storage_path_string)?;
+        contact(Path::new(storage_path_string.as_str()), m)?;
+    }
+
+    if let Some(m) = matches.subcommand_matches("graph") {
+        let storage_path_string = get_storage_path(&settings);
+        prepare_storage_area_if_needed(&storage_path_string)?;
+        let hops = match m.value_of("hops") {
+            Some(hops) => hops.parse().map_err(|_| format!("Invalid hops {:?}", hops))?,
+            None => get_hops(&settings)?,
+        };
+        for (author, distance) in social::wanted(Path::new(storage_path_string.as_str()), hops)? {
+            println!("{} {}", distance, author);
+        }
     }
     Ok(())
 }
 
+fn export_to_stdout(storage_path: &Path, matches: &ArgMatches) -> Result<()>
+{
+    use kutyus_core::message::PubKey;
+
+    let feed = if let Some(name) = matches.value_of("feed") {
+        feeds::open(storage_path, name)?.0
+    } else if let Some(author) = matches.value_of("author") {
+        let author: PubKey = author.parse()?;
+        let store = feeds::open_store(storage_path)?;
+        if !store.authors()?.contains(&author) {
+            return Err(format!("No feed of {} is stored", author).into());
+        }
+        store.feed(&author)?
+    } else {
+        feeds::open_default(storage_path)?.0
+    };
+
+    let format = matches.value_of("format").expect("unreachable").parse()?;
+    let stdout = std::io::stdout();
+    let count = transfer::export(&feed, format, &mut stdout.lock())?;
+    // stdout is the data, the summary goes to stderr
+    eprintln!(">> Exported {} messages", count);
+    Ok(())
+}
+
+fn blob(storage_path: &Path, matches: &ArgMatches) -> Result<()>
+{
+    use std::fs::File;
+    use kutyus_core::content;
+    use kutyus_core::content::blob::BlobRef;
+    use kutyus_core::message::Hash;
+
+    let store = feeds::open_blob_store(storage_path)?;
+    if let Some(m) = matches.subcommand_matches("add") {
+        let blob = match m.value_of("file").expect("unreachable") {
+            "-" => store.add(&mut std::io::stdin())?,
+            path => store.add(&mut File::open(path)?)?,
+        };
+        println!(">> Stored blob ({} bytes), hash:", blob.size);
+        println!("{}", blob.hash);
+        if m.is_present("append") {
+            let (feed, keypair) = open_feed(storage_path, m)?;
+            let (content_type, content) = content::encode(&blob)?;
+            let (sequence, _) = feeds::append(&feed, &keypair, content_type, content)?;
+            println!(">> Appended reference as message #{}", sequence);
+        }
+    } else if let Some(m) = matches.subcommand_matches("get") {
+        let hash: Hash = m.value_of("hash").expect("unreachable").parse()?;
+        let not_stored = || format!("Blob {} is not stored", hash);
+        let blob = match m.value_of("size") {
+            Some(size) => BlobRef { hash, size: size.parse().map_err(|_| format!("Invalid size {:?}", size))? },
+            None => store.reference(&hash)?.ok_or_else(not_stored)?,
+        };
+        match m.value_of("out") {
+            Some(path) => {
+                // the file is only replaced by a checked blob, the partial copy never overwrites a file
+                let temporary_path = format!("{}.{}.part", path, std::process::id());
+                let copied = {
+                    let mut temporary = std::fs::OpenOptions::new().write(true).create_new(true).open(&temporary_path)?;
+                    store.copy_to(&blob, &mut temporary)
+                };
+                match copied {
+                    Ok(true) => std::fs::rename(&temporary_path, path)?,
+                    _ => std::fs::remove_file(&temporary_path)?,
+                }
+                if !copied? {
+                    return Err(not_stored().into());
+                }
+                println!(">> Wrote blob ({} bytes) to {:?}", blob.size, path);
+            },
+            None => {
+                use std::io::Write;
+                // stdout can not be taken back, so the blob is checked in memory first
+                let bytes = store.get(&blob)?.ok_or_else(not_stored)?;
+                std::io::stdout().write_all(&bytes)?;
+            },
+        }
+    } else if let Some(m) = matches.subcommand_matches("has") {
+        let hash: Hash = m.value_of("hash").expect("unreachable").parse()?;
+        println!("{}", if store.has(&hash) { "yes" } else { "no" });
+    }
+    Ok(())
+}
+
+fn contact(storage_path: &Path, matches: &ArgMatches) -> Result<()>
+{
+    use kutyus_core::content;
+    use kutyus_core::content::contact::{Contact, Relation};
+    use kutyus_core::message::PubKey;
+
+    let (feed, keypair) = open_feed(storage_path, matches)?;
+    let contact: PubKey = matches.value_of("pubkey").expect("unreachable").parse()?;
+    let relation = match matches.value_of("relation").expect("unreachable") {
+        "unfollow" => Relation::Unfollow,
+        "block" => Relation::Block,
+        _ => Relation::Follow,
+    };
+
+    let (content_type, content) = content::encode(&Contact { contact, relation })?;
+    let (sequence, _) = feeds::append(&feed, &keypair, content_type, content)?;
+    println!(">> Appended {} of {} as message #{}", relation, contact, sequence);
+    Ok(())
+}
+
+fn about(storage_path: &Path, matches: &ArgMatches) -> Result<()>
+{
+    use kutyus_core::content;
+    use kutyus_core::content::about::About;
+    use kutyus_core::message::PubKey;
+    use kutyus::profiles;
+
+    let author = if let Some(author) = matches.value_of("author") {
+        author.parse()?
+    } else {
+        let (feed, keypair) = open_feed(storage_path, matches)?;
+        let update = About {
+            name: matches.value_of("name").map(String::from),
+            description: matches.value_of("description").map(String::from),
+            avatar: match matches.value_of("avatar") {
+                Some(hash) => Some(hash.parse()?),
+                None => None,
+            },
+        };
+        if update != About::default() {
+            let (content_type, content) = content::encode(&update)?;
+            let (sequence, _) = feeds::append(&feed, &keypair, content_type, content)?;
+            println!(">> Appended profile update #{}", sequence);
+        }
+        PubKey::from_keypair(&keypair)
+    };
+
+    let profile = profiles::resolve(&feeds::open_store(storage_path)?, &author)?;
+    println!("author:      {}", profile.author);
+    println!("name:        {}", profile.name.unwrap_or_default());
+    println!("description: {}", profile.description.unwrap_or_default());
+    println!("avatar:      {}", profile.avatar.map(|hash| hash.to_string()).unwrap_or_default());
+    Ok(())
+}
+
+fn append(storage_path: &Path, matches: &ArgMatches) -> Result<()>
+{
+    use std::io::Read;
+    use kutyus_core::compression::Compression;
+    use kutyus_core::content;
+    use kutyus_core::content::post::Post;
+    use kutyus_core::message::ContentType;
+
+    let (feed, keypair) = open_feed(storage_path, matches)?;
+
+    let mut input = Vec::new();
+    let from_stdin = match matches.value_of("text") {
+        Some(text) => {
+            input.extend_from_slice(text.as_bytes());
+            false
+        },
+        None => {
+            std::io::stdin().read_to_end(&mut input)?;
+            true
+        },
+    };
+
+    let (content_type, content) = match matches.value_of("type").expect("unreachable") {
+        "post" => {
+            let mut text = String::from_utf8(input).map_err(|_| "The text of a post must be valid UTF-8")?;
+            // the newline ending the input is not part of the text, `--text` is kept as given
+            if from_stdin && text.ends_with('\n') {
+                text.pop();
+                if text.ends_with('\r') {
+                    text.pop();
+                }
+            }
+            content::encode(&Post::new(text))?
+        },
+        _ => (ContentType::Blob, input),
+    };
+    let length = content.len();
+    let compression = if matches.is_present("compress") { Compression::Deflate } else { Compression::None };
+
+    let (sequence, id) = feeds::append_compressed(&feed, &keypair, content_type, content, compression)?;
+    match compression {
+        Compression::None => println!(">> Appended message #{} ({} bytes), id:", sequence, length),
+        _ => println!(">> Appended message #{} ({} bytes, {} compressed), id:", sequence, length, compression),
+    }
+    println!("{}", id);
+
+    Ok(())
+}
+
+/// Opens the feed given by `--feed`, or the default feed
+fn open_feed(storage_path: &Path, matches: &ArgMatches) -> Result<(Feed, KeyPair)>
+{
+    match matches.value_of("feed") {
+        Some(name) => feeds::open(storage_path, name),
+        None => feeds::open_default(storage_path),
+    }
+}
+
 fn prepare_storage_area_if_needed(path: &String) -> Result<()>
 {
     let storage_path = Path::new(path.as_str());
     create_storage_dir(storage_path)?;
-    generate_key(&storage_path.join("keys"))?;
+    generate_default_key(storage_path)?;
     Ok(())
 }
 
@@ -69,17 +348,12 @@ fn create_storage_dir(path: &Path) -> Result<()>
     Ok(())
 }
 
-fn generate_key(path: &Path) -> Result<()>
+fn generate_default_key(storage_path: &Path) -> Result<()>
 {
-    use std::io::Write;
-    if !path.exists() { std::fs::create_dir_all(path)?; }
-
-    let keyfile_path = path.join("my.key");
+    let keyfile_path = keys::key_path(storage_path, keys::DEFAULT_KEY_NAME);
     if !keyfile_path.exists() {
-        let privkey = kutyus_core::generate_private_key()?;
         println!(">> No key found, generating to {:?}", keyfile_path);
-        let mut file = std::fs::File::create(keyfile_path)?;
-        file.write_all(&privkey)?;
+        keys::generate(storage_path, keys::DEFAULT_KEY_NAME, false)?;
     }
     Ok(())
 }
@@ -100,6 +374,18 @@ fn arg_matches<'a>(default_config_path: &'a str) -> ArgMatches<'a>
         .subcommand(
             SubCommand::with_name("keygen")
             .about("Generates an Ed25519 keypair from system random (not pseudo)")
+            .arg(
+                Arg::with_name("name")
+                .short("n")
+                .long("name")
+                .value_name("NAME")
+                .help("name of the key, it is stored as keys/<NAME>.key")
+                .default_value(keys::DEFAULT_KEY_NAME)
+            )
+            .arg(Arg::with_name("force")
+                 .short("f")
+                 .long("force")
+                 .help("Overwrites the key if it already exists"))
         )
         .subcommand(
             SubCommand::with_name("init")
@@ -107,11 +393,182 @@ fn arg_matches<'a>(default_config_path: &'a str) -> ArgMatches<'a>
             .arg(Arg::with_name("force")
                  .short("f")
                  .long("force")
-                 .help("Forces re-generating config"))
+                 .help("Forces re-generating config, the previous one is backed up"))
+            .arg(Arg::with_name("wipe-storage")
+                 .long("wipe-storage")
+                 .requires("force")
+                 .help("Also removes the storage area (keys and feeds), asks for confirmation"))
         )
         .subcommand(
             SubCommand::with_name("append")
-            .about("Adds new message to your storage - WIP: uses blob content_type, reads content from stdin")
+            .about("Appends a new signed message to your feed, reads the content from stdin")
+            .arg(
+                Arg::with_name("feed")
+                .long("feed")
+                .value_name("NAME")
+                .help("name of the feed to append to, instead of the feed of your default key")
+            )
+            .arg(
+                Arg::with_name("type")
+                .long("type")
+                .value_name("TYPE")
+                .possible_values(&["blob", "post"])
+                .default_value("blob")
+                .help("content type of the message: raw bytes or a text post")
+            )
+            .arg(
+                Arg::with_name("text")
+                .long("text")
+                .value_name("TEXT")
+                .help("content of the message, instead of reading it from stdin")
+            )
+            .arg(
+                Arg::with_name("compress")
+                .long("compress")
+                .help("stores the content deflate-compressed, readers decompress it automatically")
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("about")
+            .about("Updates the profile of your feed, then shows the effective profile")
+            .arg(
+                Arg::with_name("feed")
+                .long("feed")
+                .value_name("NAME")
+                .help("name of the feed to update, instead of the feed of your default key")
+            )
+            .arg(
+                Arg::with_name("name")
+                .long("name")
+                .value_name("NAME")
+                .help("display name of the author")
+            )
+            .arg(
+                Arg::with_name("description")
+                .long("description")
+                .value_name("TEXT")
+                .help("a few words about the feed")
+            )
+            .arg(
+                Arg::with_name("avatar")
+                .long("avatar")
+                .value_name("HASH")
+                .help("hash of the avatar image blob")
+            )
+            .arg(
+                Arg::with_name("author")
+                .long("author")
+                .value_name("PUBKEY")
+                .conflicts_with_all(&["feed", "name", "description", "avatar"])
+                .help("only shows the profile of the given author")
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("blob")
+            .about("Stores and retrieves blobs, large attachments referenced by messages")
+            .subcommand(
+                SubCommand::with_name("add")
+                .about("Stores a file as a blob and prints its hash")
+                .arg(
+                    Arg::with_name("file")
+                    .value_name("FILE")
+                    .required(true)
+                    .help("the file to store, - for stdin")
+                )
+                .arg(
+                    Arg::with_name("append")
+                    .long("append")
+                    .help("also appends a blob-ref message referencing the blob to your feed")
+                )
+                .arg(
+                    Arg::with_name("feed")
+                    .long("feed")
+                    .value_name("NAME")
+                    .requires("append")
+                    .help("name of the feed to append to, instead of the feed of your default key")
+                )
+            )
+            .subcommand(
+                SubCommand::with_name("get")
+                .about("Writes a stored blob to stdout, after checking its hash, and its size if --size is given")
+                .arg(hash_arg())
+                .arg(
+                    Arg::with_name("size")
+                    .long("size")
+                    .value_name("BYTES")
+                    .help("size of the blob in the message referencing it, without it the size of the stored file is taken")
+                )
+                .arg(
+                    Arg::with_name("out")
+                    .long("out")
+                    .value_name("FILE")
+                    .help("writes the blob to FILE instead of stdout, FILE is only written once the blob is verified")
+                )
+            )
+            .subcommand(
+                SubCommand::with_name("has")
+                .about("Tells whether a blob is stored")
+                .arg(hash_arg())
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+            .about("Writes the messages of a feed to stdout")
+            .arg(
+                Arg::with_name("feed")
+                .long("feed")
+                .value_name("NAME")
+                .help("name of the feed to export, instead of the feed of your default key")
+            )
+            .arg(
+                Arg::with_name("author")
+                .long("author")
+                .value_name("PUBKEY")
+                .conflicts_with("feed")
+                .help("public key of the author whose stored feed is exported")
+            )
+            .arg(format_arg())
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+            .about("Reads messages from stdin and appends them to the feeds of their authors")
+            .arg(format_arg())
+            .arg(hops_arg().help("stores only the feeds of authors at most this many follows away [default: hops setting]"))
+            .arg(
+                Arg::with_name("all")
+                .long("all")
+                .conflicts_with("hops")
+                .help("stores the feeds of all authors")
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("contact")
+            .about("Follows, unfollows or blocks another author")
+            .arg(
+                Arg::with_name("pubkey")
+                .value_name("PUBKEY")
+                .required(true)
+                .help("public key of the other author")
+            )
+            .arg(
+                Arg::with_name("relation")
+                .long("relation")
+                .value_name("RELATION")
+                .possible_values(&["follow", "unfollow", "block"])
+                .default_value("follow")
+                .help("the new relation to the author")
+            )
+            .arg(
+                Arg::with_name("feed")
+                .long("feed")
+                .value_name("NAME")
+                .help("name of the feed to append to, instead of the feed of your default key")
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("graph")
+            .about("Lists the authors whose feeds are stored, with their follow distance from you")
+            .arg(hops_arg().help("maximum follow distance [default: hops setting]"))
         )
         .subcommand(
             SubCommand::with_name("newfeed")
@@ -124,10 +581,42 @@ fn arg_matches<'a>(default_config_path: &'a str) -> ArgMatches<'a>
                 .help("name of the new feed")
                 .required(true)
             )
+            .arg(
+                Arg::with_name("key")
+                .short("k")
+                .long("key")
+                .value_name("KEY")
+                .help("name of the key the feed is bound to, generated if it does not exist [default: NAME]")
+            )
         )
         .get_matches()
 }
 
+fn format_arg<'a, 'b>() -> Arg<'a, 'b>
+{
+    Arg::with_name("format")
+        .long("format")
+        .value_name("FORMAT")
+        .possible_values(transfer::FORMAT_NAMES)
+        .default_value("binary")
+        .help("binary: concatenated msgpack frames, json: one JSON object per line")
+}
+
+fn hash_arg<'a, 'b>() -> Arg<'a, 'b>
+{
+    Arg::with_name("hash")
+        .value_name("HASH")
+        .required(true)
+        .help("SHA-512 hash of the blob, in hex")
+}
+
+fn hops_arg<'a, 'b>() -> Arg<'a, 'b>
+{
+    Arg::with_name("hops")
+        .long("hops")
+        .value_name("N")
+}
+
 fn default_config_path() -> String
 {
     let mut config_dir_path: PathBuf = env::var_os("XDG_CONFIG_HOME")
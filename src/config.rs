@@ -1,17 +1,42 @@
 
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use config_crate::Config;
 
-use ::errors::Result;
+use ::errors::{Result, ResultExt};
 
-pub fn init(path: &Path, force: bool) -> Result<()>
+/// Creates the default config at `path`
+///
+/// An existing config is only replaced if `force` is set, after backing it up
+/// next to the original one. If `wipe_storage` is also set, the storage area
+/// of the old config is removed too, but only after an explicit confirmation.
+pub fn init(path: &Path, force: bool, wipe_storage: bool) -> Result<()>
 {
     println!("Initializing kutyus-rs instance...");
     if path.exists() {
         if force {
-            bail!("Not implemented");
+            let storage_path = if wipe_storage {
+                let path_str = path.to_str().ok_or("Config path is not valid unicode")?;
+                Some(get_storage_path(&load_config(path_str)?))
+            } else {
+                None
+            };
+
+            if let Some(ref storage_path) = storage_path {
+                let stdin = io::stdin();
+                if !confirm_wipe(storage_path, stdin.lock())? {
+                    bail!("Aborted, nothing has been changed");
+                }
+            }
+
+            let backup_path = backup_config(path)?;
+            println!(">> Saved previous config to {:?}", backup_path);
+
+            if let Some(storage_path) = storage_path {
+                wipe_storage_area(Path::new(storage_path.as_str()))?;
+            }
+            create_initial_config(path)?;
         } else {
             bail!("Already initialized! Use --force to destroy previous instance");
         }
@@ -28,6 +53,8 @@ pub fn load_config(path: &str) -> Result<Config>
 
     settings
         .set_default("storage", expand_path("~/.kutyus-rs/storage".into()))?;
+    settings
+        .set_default("hops", i64::from(DEFAULT_HOPS))?;
 
     settings
         .merge(::config_crate::File::with_name(path))?;
@@ -40,6 +67,21 @@ pub fn get_storage_path(settings: &Config) -> String
     expand_path(settings.get_str("storage").expect("unreachable"))
 }
 
+/// Default of the `hops` setting, see [`get_hops`]
+///
+/// [`get_hops`]: fn.get_hops.html
+pub const DEFAULT_HOPS: u32 = 2;
+
+/// The follow distance up to which feeds are stored, from the `hops` setting
+pub fn get_hops(settings: &Config) -> Result<u32>
+{
+    let hops = settings.get_int("hops")?;
+    if hops < 0 || hops > i64::from(!0u32) {
+        bail!("Invalid hops setting {}, it should be a non-negative number", hops);
+    }
+    Ok(hops as u32)
+}
+
 fn expand_path(path: String) -> String
 {
     use std::env;
@@ -51,6 +93,51 @@ fn expand_path(path: String) -> String
 }
 
 
+/// Copies the config to `<path>.<unix timestamp>.bak` and returns the path of the copy
+fn backup_config(path: &Path) -> Result<PathBuf>
+{
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .chain_err(|| "System clock is before 1970")?
+        .as_secs();
+    let file_name = path.file_name().ok_or("Config path has no file name")?.to_string_lossy();
+
+    let mut backup_path = path.with_file_name(format!("{}.{}.bak", file_name, timestamp));
+    let mut counter = 1;
+    while backup_path.exists() {
+        backup_path = path.with_file_name(format!("{}.{}-{}.bak", file_name, timestamp, counter));
+        counter += 1;
+    }
+
+    fs::copy(path, &backup_path)?;
+    Ok(backup_path)
+}
+
+/// Asks the user to type `yes` before wiping the storage area
+fn confirm_wipe<R>(storage_path: &str, mut input: R) -> Result<bool>
+    where R: io::BufRead
+{
+    use std::io::Write;
+    print!("This removes all keys and feeds in {:?}! Type 'yes' to continue: ", storage_path);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim() == "yes")
+}
+
+fn wipe_storage_area(storage_path: &Path) -> Result<()>
+{
+    use std::fs;
+    if storage_path.exists() {
+        fs::remove_dir_all(storage_path)?;
+        println!(">> Removed storage area {:?}", storage_path);
+    }
+    Ok(())
+}
+
 fn create_initial_config(path: &Path) -> io::Result<()>
 {
     use std::fs;
@@ -71,4 +158,72 @@ r#"
 
 # Path of your feed-storage
 # storage = "~/.kutyus-rs/storage/"
+
+# Feeds of authors up to this many follows away from you are stored on import
+# hops = 2
 "#;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn forced_init_backs_up_and_regenerates_the_config()
+    {
+        let dir = TempDir::new("config").unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "storage = \"/somewhere\"\n").unwrap();
+
+        assert!(init(&path, false, false).is_err());
+        init(&path, true, false).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG_FILE);
+        let backups: Vec<_> = fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "storage = \"/somewhere\"\n");
+    }
+
+    #[test]
+    fn backups_do_not_overwrite_each_other()
+    {
+        let dir = TempDir::new("config").unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "first").unwrap();
+        let first = backup_config(&path).unwrap();
+        fs::write(&path, "second").unwrap();
+        let second = backup_config(&path).unwrap();
+
+        assert!(first != second);
+        assert_eq!(fs::read_to_string(first).unwrap(), "first");
+        assert_eq!(fs::read_to_string(second).unwrap(), "second");
+    }
+
+    #[test]
+    fn hops_has_a_default_and_must_not_be_negative()
+    {
+        let dir = TempDir::new("config").unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "storage = \"/somewhere\"\n").unwrap();
+        assert_eq!(get_hops(&load_config(path.to_str().unwrap()).unwrap()).unwrap(), DEFAULT_HOPS);
+
+        fs::write(&path, "hops = 1\n").unwrap();
+        assert_eq!(get_hops(&load_config(path.to_str().unwrap()).unwrap()).unwrap(), 1);
+
+        fs::write(&path, "hops = -1\n").unwrap();
+        assert!(get_hops(&load_config(path.to_str().unwrap()).unwrap()).is_err());
+    }
+
+    #[test]
+    fn only_yes_confirms_wiping()
+    {
+        assert!(confirm_wipe("/storage", io::Cursor::new("yes\n")).unwrap());
+        assert!(!confirm_wipe("/storage", io::Cursor::new("y\n")).unwrap());
+        assert!(!confirm_wipe("/storage", io::Cursor::new("")).unwrap());
+    }
+}
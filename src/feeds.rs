@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use kutyus_core::KeyPair;
+use kutyus_core::compression::Compression;
+use kutyus_core::content;
+use kutyus_core::content::about::About;
+use kutyus_core::frame::Frame;
+use kutyus_core::message::{ContentType, Message, MessageId, PubKey, Stamp};
+use kutyus_core::errors::{Error as CoreError, ErrorKind as CoreErrorKind};
+use kutyus_core::options::DecodeLimits;
+use kutyus_persistence::{BlobStore, Feed, FeedStore};
+
+use ::errors::Result;
+use ::keys;
+
+/// Content type of the root message of a named feed, the content is the name of the feed
+pub const FEED_CONTENT_TYPE: &[u8] = b"feed";
+
+/// File in the feed directory containing the name of the key the feed is bound to
+const KEY_BINDING_FILE: &str = "key";
+
+/// Opens the feed store of the storage area
+pub fn open_store(storage_path: &Path) -> Result<FeedStore>
+{
+    Ok(FeedStore::open(storage_path.join("feeds"))?)
+}
+
+/// Opens the blob store of the storage area
+pub fn open_blob_store(storage_path: &Path) -> Result<BlobStore>
+{
+    Ok(BlobStore::open(storage_path.join("blobs"))?)
+}
+
+/// Creates the feed called `name` bound to the existing key `key_name`
+///
+/// The root message of the feed is written immediately, with [`FEED_CONTENT_TYPE`],
+/// followed by an `about` message setting the name as the display name of the author.
+/// A key can be bound to only one feed, otherwise the feeds would fork, so the
+/// default key can not be bound at all.
+///
+/// [`FEED_CONTENT_TYPE`]: constant.FEED_CONTENT_TYPE.html
+pub fn create(storage_path: &Path, name: &str, key_name: &str) -> Result<PubKey>
+{
+    let store = open_store(storage_path)?;
+    if store.has_named(name)? {
+        bail!("Feed {:?} already exists!", name);
+    }
+    if key_name == keys::DEFAULT_KEY_NAME {
+        bail!("The default key {:?} can not be bound to a named feed, use `--key` to choose another one",
+              key_name);
+    }
+
+    let keypair = keys::load(storage_path, key_name)?;
+    let author = PubKey::from_keypair(&keypair);
+    if store.has_feed(&author)? && !store.feed(&author)?.is_empty()? {
+        bail!("Key {:?} is already used by the default feed", key_name);
+    }
+    if let Some(other) = feed_bound_to(&store, key_name)? {
+        bail!("Key {:?} is already bound to feed {:?}", key_name, other);
+    }
+
+    // encoding validates the name, nothing is stored for an invalid one
+    let (content_type, content) = content::encode(&About::name(name))?;
+    let feed = store.named(name)?;
+    fs::write(store.named_path(name)?.join(KEY_BINDING_FILE), key_name)?;
+    append(&feed, &keypair, ContentType::Custom(FEED_CONTENT_TYPE.to_vec()), name.as_bytes().to_vec())?;
+    append(&feed, &keypair, content_type, content)?;
+
+    Ok(author)
+}
+
+/// Opens the feed called `name` with the key it is bound to
+pub fn open(storage_path: &Path, name: &str) -> Result<(Feed, KeyPair)>
+{
+    let store = open_store(storage_path)?;
+    if !store.has_named(name)? {
+        bail!("Feed {:?} does not exist! Use `ku newfeed --name {}` to create it", name, name);
+    }
+    let keypair = keys::load(storage_path, &bound_key_name(&store, name)?)?;
+    Ok((store.named(name)?, keypair))
+}
+
+/// Opens the default feed, the one of the default key
+///
+/// Fails if the default key is bound to a named feed, appending under the key
+/// would fork the named feed.
+pub fn open_default(storage_path: &Path) -> Result<(Feed, KeyPair)>
+{
+    let store = open_store(storage_path)?;
+    if let Some(name) = feed_bound_to(&store, keys::DEFAULT_KEY_NAME)? {
+        bail!("The default key is bound to feed {:?}, use `--feed {}` to append to it", name, name);
+    }
+    let keypair = keys::load(storage_path, keys::DEFAULT_KEY_NAME)?;
+    let feed = store.feed(&PubKey::from_keypair(&keypair))?;
+    Ok((feed, keypair))
+}
+
+/// The names of the named feeds, by the author of their root message
+pub fn named_authors(store: &FeedStore) -> Result<HashMap<PubKey, String>>
+{
+    let mut named = HashMap::new();
+    for name in store.names()? {
+        if let Some(root) = store.named(&name)?.get(0)? {
+            named.insert(root.decode_message()?.author, name);
+        }
+    }
+    Ok(named)
+}
+
+/// The stored feed of `author`: the named feed bound to their key if there is one,
+/// otherwise the feed under their key, if it exists
+///
+/// Decodes the root of every named feed, use [`feed_in`] to look up many authors.
+///
+/// [`feed_in`]: fn.feed_in.html
+pub fn feed_of(store: &FeedStore, author: &PubKey) -> Result<Option<Feed>>
+{
+    feed_in(store, &named_authors(store)?, author)
+}
+
+/// Same as [`feed_of`], with the named feeds already listed by [`named_authors`]
+///
+/// [`feed_of`]: fn.feed_of.html
+/// [`named_authors`]: fn.named_authors.html
+pub fn feed_in(store: &FeedStore, named: &HashMap<PubKey, String>, author: &PubKey) -> Result<Option<Feed>>
+{
+    if let Some(name) = named.get(author) {
+        return Ok(Some(store.named(name)?));
+    }
+    if store.has_feed(author)? {
+        return Ok(Some(store.feed(author)?));
+    }
+    Ok(None)
+}
+
+/// Signs a new message chained to the head of the `feed` and appends it
+///
+/// The message is stamped with its sequence number and the current time.
+/// Returns the sequence number and the identifier of the new message.
+pub fn append(feed: &Feed, keypair: &KeyPair, content_type: ContentType, content: Vec<u8>) -> Result<(u64, MessageId)>
+{
+    append_compressed(feed, keypair, content_type, content, Compression::None)
+}
+
+/// Same as [`append`], storing the content compressed with `compression`
+///
+/// [`append`]: fn.append.html
+pub fn append_compressed(feed: &Feed, keypair: &KeyPair, content_type: ContentType, content: Vec<u8>,
+                         compression: Compression) -> Result<(u64, MessageId)>
+{
+    // a message above the default limits could not be read back by other peers
+    let limits = DecodeLimits::default();
+    check_limit("content type", content_type.as_bytes().len(), limits.max_content_type_len)?;
+    let content = match compression {
+        Compression::None => content,
+        compression => {
+            check_limit("decompressed content", content.len(), limits.max_decompressed_len)?;
+            compression.compress(&content).into_owned()
+        },
+    };
+    check_limit("content", content.len(), limits.max_content_len)?;
+
+    let parent = feed.head()?.map(|head| head.message_id().into());
+    let message = Message {
+        author: PubKey::from_keypair(keypair),
+        parent,
+        stamp: Some(Stamp::now(feed.len()?)),
+        compression,
+        content_type,
+        content,
+    };
+    check_limit("message", message.encoded_len(), limits.max_message_size)?;
+    let frame = Frame::new_signed(&message, keypair)?;
+    let sequence = feed.append(&frame)?;
+    Ok((sequence, frame.message_id()))
+}
+
+fn bound_key_name(store: &FeedStore, name: &str) -> Result<String>
+{
+    let binding_path = store.named_path(name)?.join(KEY_BINDING_FILE);
+    let key_name = fs::read_to_string(binding_path)?;
+    Ok(key_name.trim().to_string())
+}
+
+/// Fails the same way as decoding would if `length` of `field` is above `limit`
+fn check_limit(field: &str, length: usize, limit: u32) -> Result<()>
+{
+    if length as u64 > u64::from(limit) {
+        let kind = CoreErrorKind::LimitExceeded(field.to_string(), length as u64, u64::from(limit));
+        return Err(CoreError::from(kind).into());
+    }
+    Ok(())
+}
+
+/// The name of the feed bound to the key `key_name`, if any
+fn feed_bound_to(store: &FeedStore, key_name: &str) -> Result<Option<String>>
+{
+    for name in store.names()? {
+        if store.named_path(&name)?.join(KEY_BINDING_FILE).exists() && bound_key_name(store, &name)? == key_name {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use kutyus_core::options::{DEFAULT_MAX_CONTENT_LEN, DEFAULT_MAX_CONTENT_TYPE_LEN, DEFAULT_MAX_DECOMPRESSED_LEN};
+    use kutyus_core::validator::FeedValidator;
+
+    #[test]
+    fn created_feed_starts_with_a_signed_root_message()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        let author = keys::generate(dir.path(), "bot", false).unwrap();
+        assert_eq!(create(dir.path(), "news", "bot").unwrap(), author);
+
+        let (feed, keypair) = open(dir.path(), "news").unwrap();
+        append(&feed, &keypair, ContentType::Blob, vec![1u8, 2u8]).unwrap();
+
+        let frames: Vec<Frame> = feed.frames().unwrap().map(|frame| frame.unwrap()).collect();
+        let mut validator = FeedValidator::new();
+        let root = validator.validate(&frames[0]).unwrap();
+        assert_eq!(root.author, author);
+        assert_eq!(root.content_type, ContentType::Custom(FEED_CONTENT_TYPE.to_vec()));
+        assert_eq!(root.content, b"news".to_vec());
+        assert_eq!(root.stamp.unwrap().sequence, 0);
+        let about = validator.validate(&frames[1]).unwrap();
+        assert_eq!(about.decode_content::<About>().unwrap(), About::name("news"));
+        assert_eq!(about.stamp.unwrap().sequence, 1);
+        assert_eq!(validator.validate(&frames[2]).unwrap().stamp.unwrap().sequence, 2);
+    }
+
+    #[test]
+    fn feed_can_not_be_created_twice()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        keys::generate(dir.path(), "bot", false).unwrap();
+        create(dir.path(), "news", "bot").unwrap();
+        assert!(create(dir.path(), "news", "bot").is_err());
+    }
+
+    #[test]
+    fn name_too_long_for_the_profile_leaves_no_feed()
+    {
+        use kutyus_core::content::about::MAX_NAME_LEN;
+
+        let dir = TempDir::new("feeds").unwrap();
+        keys::generate(dir.path(), "bot", false).unwrap();
+        let name = "n".repeat(MAX_NAME_LEN + 1);
+        assert!(create(dir.path(), &name, "bot").is_err());
+
+        let store = open_store(dir.path()).unwrap();
+        assert!(!store.has_named(&name).unwrap());
+        assert_eq!(feed_bound_to(&store, "bot").unwrap(), None);
+        create(dir.path(), "news", "bot").unwrap();
+    }
+
+    #[test]
+    fn key_can_be_bound_to_only_one_feed()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        keys::generate(dir.path(), "bot", false).unwrap();
+        create(dir.path(), "news", "bot").unwrap();
+        assert!(create(dir.path(), "weather", "bot").is_err());
+        assert!(!open_store(dir.path()).unwrap().has_named("weather").unwrap());
+    }
+
+    #[test]
+    fn key_of_the_default_feed_can_not_be_bound()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let (feed, keypair) = open_default(dir.path()).unwrap();
+        append(&feed, &keypair, ContentType::Blob, vec![]).unwrap();
+
+        assert!(create(dir.path(), "news", keys::DEFAULT_KEY_NAME).is_err());
+    }
+
+    #[test]
+    fn default_key_can_not_be_bound_to_a_named_feed()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        let author = keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        assert!(create(dir.path(), "news", keys::DEFAULT_KEY_NAME).is_err());
+        assert!(create(dir.path(), keys::DEFAULT_KEY_NAME, keys::DEFAULT_KEY_NAME).is_err());
+        let store = open_store(dir.path()).unwrap();
+        assert!(!store.has_feed(&author).unwrap());
+        assert!(store.names().unwrap().is_empty());
+
+        // a binding made before the check, the default feed must not fork it
+        store.named("news").unwrap();
+        fs::write(store.named_path("news").unwrap().join(KEY_BINDING_FILE), keys::DEFAULT_KEY_NAME).unwrap();
+        assert!(open_default(dir.path()).is_err());
+        assert!(!store.has_feed(&author).unwrap());
+    }
+
+    #[test]
+    fn rejected_binding_leaves_no_feed_directory()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        let author = keys::generate(dir.path(), "bot", false).unwrap();
+        create(dir.path(), "news", "bot").unwrap();
+        assert!(create(dir.path(), "weather", "bot").is_err());
+        assert!(!open_store(dir.path()).unwrap().has_feed(&author).unwrap());
+    }
+
+    #[test]
+    fn content_above_the_default_limit_is_not_appended()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let (feed, keypair) = open_default(dir.path()).unwrap();
+
+        let content = vec![0u8; DEFAULT_MAX_CONTENT_LEN as usize + 1];
+        assert!(append(&feed, &keypair, ContentType::Blob, content).is_err());
+        let content_type = ContentType::Custom(vec![b'x'; DEFAULT_MAX_CONTENT_TYPE_LEN as usize + 1]);
+        assert!(append(&feed, &keypair, content_type, vec![]).is_err());
+        assert_eq!(feed.len().unwrap(), 0);
+    }
+
+    #[test]
+    fn compressed_content_is_stored_in_a_version_3_message()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let (feed, keypair) = open_default(dir.path()).unwrap();
+
+        // compresses below the content limit, the decompressed size is checked instead
+        let content = vec![7u8; DEFAULT_MAX_CONTENT_LEN as usize + 1];
+        append_compressed(&feed, &keypair, ContentType::Blob, content.clone(), Compression::Deflate).unwrap();
+
+        let frame = feed.frames().unwrap().next().unwrap().unwrap();
+        assert_eq!(frame.version, 3);
+        let message = FeedValidator::new().validate(&frame).unwrap();
+        assert!(message.content.len() < content.len());
+        assert_eq!(message.decompressed_content(&DecodeLimits::default()).unwrap().into_owned(), content);
+
+        let bomb = vec![0u8; DEFAULT_MAX_DECOMPRESSED_LEN as usize + 1];
+        assert!(append_compressed(&feed, &keypair, ContentType::Blob, bomb, Compression::Deflate).is_err());
+        assert_eq!(feed.len().unwrap(), 1);
+    }
+
+    #[test]
+    fn named_feed_is_found_by_its_author()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        let author = keys::generate(dir.path(), "bot", false).unwrap();
+        create(dir.path(), "news", "bot").unwrap();
+
+        let store = open_store(dir.path()).unwrap();
+        let named = named_authors(&store).unwrap();
+        assert_eq!(named.get(&author), Some(&"news".to_string()));
+        assert_eq!(feed_in(&store, &named, &author).unwrap().unwrap().len().unwrap(), 2);
+        assert!(feed_in(&store, &named, &PubKey([1u8; 32])).unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_feed_can_not_be_opened()
+    {
+        let dir = TempDir::new("feeds").unwrap();
+        assert!(open(dir.path(), "nothing").is_err());
+    }
+}
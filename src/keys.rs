@@ -0,0 +1,147 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use kutyus_core::{KeyPair, SecretKey};
+use kutyus_core::message::PubKey;
+
+use ::errors::Result;
+
+/// Name of the key used when no other key is given
+pub const DEFAULT_KEY_NAME: &str = "my";
+
+/// Path of the key called `name` in the storage area
+pub fn key_path(storage_path: &Path, name: &str) -> PathBuf
+{
+    storage_path.join("keys").join(format!("{}.key", name))
+}
+
+/// Generates a new PKCS#8 encoded Ed25519 key called `name`
+///
+/// An existing key is only overwritten if `force` is set.
+/// Returns the public key of the new keypair.
+pub fn generate(storage_path: &Path, name: &str, force: bool) -> Result<PubKey>
+{
+    check_name(name)?;
+    let path = key_path(storage_path, name);
+    if path.exists() && !force {
+        bail!("Key {:?} already exists! Use --force to overwrite it", name);
+    }
+
+    let privkey = SecretKey::generate()?;
+    write_private(&path, privkey.as_bytes())?;
+
+    let keypair = privkey.keypair()?;
+    Ok(PubKey::from_keypair(&keypair))
+}
+
+/// Loads the key called `name` from the storage area
+pub fn load(storage_path: &Path, name: &str) -> Result<KeyPair>
+{
+    check_name(name)?;
+    let path = key_path(storage_path, name);
+    if !path.exists() {
+        bail!("Key {:?} does not exist! Use `ku keygen --name {}` to create it", name, name);
+    }
+    let privkey = SecretKey::from_bytes(fs::read(path)?);
+    Ok(privkey.keypair()?)
+}
+
+fn check_name(name: &str) -> Result<()>
+{
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("Invalid key name {:?}: use only letters, digits, '-' and '_'", name);
+    }
+    Ok(())
+}
+
+/// Writes the file readable only by the owner
+///
+/// The key is written to a temporary file first, so an overwritten key
+/// is never left half-written.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()>
+{
+    let directory = path.parent().expect("Key path should have a parent directory");
+    fs::create_dir_all(directory)?;
+
+    let temporary_path = path.with_extension("key.tmp");
+    if temporary_path.exists() {
+        // left behind by an interrupted write
+        fs::remove_file(&temporary_path)?;
+    }
+    {
+        let mut file = create_private_file(&temporary_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temporary_path, path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> Result<fs::File>
+{
+    use std::os::unix::fs::OpenOptionsExt;
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> Result<fs::File>
+{
+    Ok(fs::OpenOptions::new().write(true).create_new(true).open(path)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn generated_key_can_be_loaded()
+    {
+        let dir = TempDir::new("keys").unwrap();
+        let pubkey = generate(dir.path(), "bot-1", false).unwrap();
+        let keypair = load(dir.path(), "bot-1").unwrap();
+        assert_eq!(PubKey::from_keypair(&keypair), pubkey);
+    }
+
+    #[test]
+    fn existing_key_is_only_overwritten_with_force()
+    {
+        let dir = TempDir::new("keys").unwrap();
+        let first = generate(dir.path(), "bot", false).unwrap();
+        assert!(generate(dir.path(), "bot", false).is_err());
+        assert_eq!(PubKey::from_keypair(&load(dir.path(), "bot").unwrap()), first);
+
+        let second = generate(dir.path(), "bot", true).unwrap();
+        assert!(first != second);
+        assert_eq!(PubKey::from_keypair(&load(dir.path(), "bot").unwrap()), second);
+    }
+
+    #[test]
+    fn invalid_names_are_rejected()
+    {
+        let dir = TempDir::new("keys").unwrap();
+        assert!(generate(dir.path(), "", false).is_err());
+        assert!(generate(dir.path(), "../escape", false).is_err());
+        assert!(load(dir.path(), "a/b").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_is_readable_only_by_the_owner()
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new("keys").unwrap();
+        generate(dir.path(), "secret", false).unwrap();
+        let mode = fs::metadata(key_path(dir.path(), "secret")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
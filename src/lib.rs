@@ -1,10 +1,14 @@
 
 extern crate kutyus_core;
+extern crate kutyus_persistence;
 
 #[macro_use]
 extern crate error_chain;
 extern crate config as config_crate;
 
+#[cfg(test)]
+extern crate tempdir;
+
 pub mod errors {
     error_chain!{
         foreign_links {
@@ -14,8 +18,14 @@ pub mod errors {
 
         links {
             Core(::kutyus_core::errors::Error, ::kutyus_core::errors::ErrorKind);
+            Persistence(::kutyus_persistence::errors::Error, ::kutyus_persistence::errors::ErrorKind);
         }
     }
 }
 
 pub mod config;
+pub mod keys;
+pub mod feeds;
+pub mod profiles;
+pub mod social;
+pub mod transfer;
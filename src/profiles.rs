@@ -0,0 +1,147 @@
+use kutyus_core::content::ContentCodec;
+use kutyus_core::content::about::About;
+use kutyus_core::message::{Hash, PubKey};
+use kutyus_persistence::FeedStore;
+
+use ::errors::Result;
+use ::feeds;
+
+/// The effective profile of an author, resolved from the `about` messages of their feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub author: PubKey,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub avatar: Option<Hash>,
+}
+
+impl Profile {
+    /// The profile of an author without `about` messages
+    pub fn new(author: PubKey) -> Profile
+    {
+        Profile { author, name: None, description: None, avatar: None }
+    }
+
+    /// Applies a later update, it overrides only the fields it sets
+    pub fn apply(&mut self, about: &About)
+    {
+        if about.name.is_some() {
+            self.name = about.name.clone();
+        }
+        if about.description.is_some() {
+            self.description = about.description.clone();
+        }
+        if about.avatar.is_some() {
+            self.avatar = about.avatar;
+        }
+    }
+
+    /// The name to show for the author, the public key if no name is set
+    pub fn display_name(&self) -> String
+    {
+        match self.name {
+            Some(ref name) => name.clone(),
+            None => self.author.to_string(),
+        }
+    }
+}
+
+/// Resolves the profile of `author` from their stored feed, see [`feeds::feed_of`]
+///
+/// The `about` messages are applied in feed order, invalid ones are ignored.
+///
+/// [`feeds::feed_of`]: ../feeds/fn.feed_of.html
+pub fn resolve(store: &FeedStore, author: &PubKey) -> Result<Profile>
+{
+    let mut profile = Profile::new(*author);
+    if let Some(feed) = feeds::feed_of(store, author)? {
+        for frame in feed.frames()? {
+            let message = frame?.decode_message()?;
+            if message.content_type.as_bytes() != About::CONTENT_TYPE || message.author != *author {
+                continue;
+            }
+            if let Ok(about) = message.decode_content::<About>() {
+                profile.apply(&about);
+            }
+        }
+    }
+    Ok(profile)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use kutyus_core::content;
+    use kutyus_core::message::ContentType;
+    use ::keys;
+
+    #[test]
+    fn latest_value_of_every_field_is_effective()
+    {
+        let dir = TempDir::new("profiles").unwrap();
+        let author = keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let (feed, keypair) = feeds::open_default(dir.path()).unwrap();
+        let post_about = |about: &About| {
+            let (content_type, content) = content::encode(about).unwrap();
+            feeds::append(&feed, &keypair, content_type, content).unwrap();
+        };
+
+        post_about(&About::name("Bob"));
+        post_about(&About { description: Some("dogs".to_string()), avatar: Some(Hash([1u8; 64])), ..About::default() });
+        feeds::append(&feed, &keypair, ContentType::Blob, b"not an about".to_vec()).unwrap();
+        post_about(&About::name("Robert"));
+
+        let profile = resolve(&feeds::open_store(dir.path()).unwrap(), &author).unwrap();
+        assert_eq!(profile, Profile {
+            author,
+            name: Some("Robert".to_string()),
+            description: Some("dogs".to_string()),
+            avatar: Some(Hash([1u8; 64])),
+        });
+        assert_eq!(profile.display_name(), "Robert");
+    }
+
+    #[test]
+    fn invalid_about_is_ignored()
+    {
+        let dir = TempDir::new("profiles").unwrap();
+        let author = keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let (feed, keypair) = feeds::open_default(dir.path()).unwrap();
+        feeds::append(&feed, &keypair, About::content_type(), vec![0x93, 0x90]).unwrap();
+
+        let profile = resolve(&feeds::open_store(dir.path()).unwrap(), &author).unwrap();
+        assert_eq!(profile, Profile::new(author));
+        assert_eq!(profile.display_name(), author.to_string());
+    }
+
+    #[test]
+    fn named_feed_has_its_name_in_the_profile()
+    {
+        let dir = TempDir::new("profiles").unwrap();
+        keys::generate(dir.path(), "bot", false).unwrap();
+        let author = feeds::create(dir.path(), "news", "bot").unwrap();
+
+        let profile = resolve(&feeds::open_store(dir.path()).unwrap(), &author).unwrap();
+        assert_eq!(profile.name, Some("news".to_string()));
+    }
+
+    #[test]
+    fn feed_under_the_key_is_ignored_next_to_the_named_feed()
+    {
+        let dir = TempDir::new("profiles").unwrap();
+        keys::generate(dir.path(), "bot", false).unwrap();
+        let author = feeds::create(dir.path(), "news", "bot").unwrap();
+
+        let store = feeds::open_store(dir.path()).unwrap();
+        let keypair = keys::load(dir.path(), "bot").unwrap();
+        let stale = About { description: Some("stale".to_string()), ..About::default() };
+        let (content_type, content) = content::encode(&stale).unwrap();
+        feeds::append(&store.feed(&author).unwrap(), &keypair, content_type, content).unwrap();
+
+        let profile = resolve(&store, &author).unwrap();
+        assert_eq!(profile.name, Some("news".to_string()));
+        assert_eq!(profile.description, None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use kutyus_core::graph::SocialGraph;
+use kutyus_core::message::PubKey;
+use kutyus_persistence::FeedStore;
+
+use ::errors::Result;
+use ::feeds;
+use ::keys;
+
+/// Folds the `contact` messages of the stored feed of every author into a [`SocialGraph`]
+///
+/// Like [`feeds::feed_in`], the named feed of an author, as listed in `named`,
+/// is used instead of the feed under their key.
+///
+/// [`SocialGraph`]: ../../kutyus_core/graph/struct.SocialGraph.html
+/// [`feeds::feed_in`]: ../feeds/fn.feed_in.html
+pub fn load(store: &FeedStore, named: &HashMap<PubKey, String>) -> Result<SocialGraph>
+{
+    let mut graph = SocialGraph::new();
+    let mut stored = Vec::new();
+    for name in named.values() {
+        stored.push(store.named(name)?);
+    }
+    for author in store.authors()? {
+        if !named.contains_key(&author) {
+            stored.push(store.feed(&author)?);
+        }
+    }
+    for feed in stored {
+        for frame in feed.frames()? {
+            graph.apply_message(&frame?.decode_message()?);
+        }
+    }
+    Ok(graph)
+}
+
+/// Our own authors: the one of the default key and the ones of the named feeds
+pub fn own_authors(storage_path: &Path) -> Result<Vec<PubKey>>
+{
+    let mut authors = Vec::new();
+    if keys::key_path(storage_path, keys::DEFAULT_KEY_NAME).exists() {
+        let keypair = keys::load(storage_path, keys::DEFAULT_KEY_NAME)?;
+        authors.push(PubKey::from_keypair(&keypair));
+    }
+    let store = feeds::open_store(storage_path)?;
+    for name in store.names()? {
+        if let Some(root) = store.named(&name)?.get(0)? {
+            authors.push(root.decode_message()?.author);
+        }
+    }
+    Ok(authors)
+}
+
+/// The authors whose feeds we store, with their follow distance from our own authors
+pub fn wanted(storage_path: &Path, max_hops: u32) -> Result<BTreeMap<PubKey, u32>>
+{
+    let store = feeds::open_store(storage_path)?;
+    let graph = load(&store, &feeds::named_authors(&store)?)?;
+    Ok(graph.hops(&own_authors(storage_path)?, max_hops))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use kutyus_core::content;
+    use kutyus_core::content::contact::{Contact, Relation};
+
+    #[test]
+    fn follows_of_our_feeds_are_wanted()
+    {
+        let dir = TempDir::new("social").unwrap();
+        let me = keys::generate(dir.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        keys::generate(dir.path(), "bot", false).unwrap();
+        let bot = feeds::create(dir.path(), "news", "bot").unwrap();
+        let friend = PubKey([1u8; 32]);
+        let stranger = PubKey([2u8; 32]);
+
+        let (feed, keypair) = feeds::open_default(dir.path()).unwrap();
+        for &(contact, relation) in &[(friend, Relation::Follow), (stranger, Relation::Follow), (stranger, Relation::Unfollow)] {
+            let (content_type, content) = content::encode(&Contact { contact, relation }).unwrap();
+            feeds::append(&feed, &keypair, content_type, content).unwrap();
+        }
+
+        let wanted = wanted(dir.path(), 1).unwrap();
+        let expected: BTreeMap<PubKey, u32> = [(me, 0), (bot, 0), (friend, 1)].iter().cloned().collect();
+        assert_eq!(wanted, expected);
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use kutyus_core::frame::Frame;
+use kutyus_core::graph::SocialGraph;
+use kutyus_core::errors::{Error as CoreError, ErrorKind as CoreErrorKind};
+use kutyus_core::json;
+use kutyus_core::message::{MessageId, PubKey};
+use kutyus_core::options::DecodeLimits;
+use kutyus_core::reader::FrameReader;
+use kutyus_core::validator::FeedValidator;
+use kutyus_persistence::{Feed, FeedStore};
+
+use ::errors::{Error, Result};
+use ::feeds;
+use ::social;
+
+/// Format of exported and imported frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Concatenated msgpack frames, as they are stored
+    Binary,
+    /// One JSON object per line, see `kutyus_core::json`
+    Json,
+}
+
+/// Names of the formats, as accepted by `Format::from_str`
+pub const FORMAT_NAMES: &[&str] = &["binary", "json"];
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Format>
+    {
+        match name {
+            "binary" => Ok(Format::Binary),
+            "json" => Ok(Format::Json),
+            _ => bail!("Unknown format {:?}, it should be one of {:?}", name, FORMAT_NAMES),
+        }
+    }
+}
+
+/// Writes all frames of the `feed` to `writer` and returns their number
+pub fn export<W>(feed: &Feed, format: Format, writer: &mut W) -> Result<u64>
+    where W: Write
+{
+    let mut count = 0;
+    for frame in feed.frames()? {
+        let frame = frame?;
+        match format {
+            Format::Binary => { frame.write(writer)?; },
+            Format::Json => writeln!(writer, "{}", json::to_string(&frame)?)?,
+        }
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Which feeds an [`import`] stores
+///
+/// [`import`]: fn.import.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// The feeds of every author
+    All,
+    /// The feeds of authors at most this many follows away from our own authors,
+    /// see [`social::wanted`]
+    ///
+    /// [`social::wanted`]: ../social/fn.wanted.html
+    Hops(u32),
+}
+
+/// Outcome of an [`import`]
+///
+/// [`import`]: fn.import.html
+#[derive(Debug, Default, PartialEq)]
+pub struct Imported {
+    /// Number of frames appended to the feeds
+    pub appended: u64,
+    /// Number of frames that were already stored
+    pub skipped: u64,
+    /// Number of frames of authors out of the [`Scope`]
+    ///
+    /// [`Scope`]: enum.Scope.html
+    pub ignored: u64,
+}
+
+/// Reads frames from `reader` and appends them to the feeds of their authors in the `scope`
+///
+/// Frames that are already stored are skipped, any other frame must continue
+/// the feed of its author, otherwise the import stops with an error. The frames
+/// appended before the error are kept.
+///
+/// The follows imported from our own feeds and the feeds in the scope extend
+/// the scope. The frames of the authors out of the scope are kept until the end
+/// of the import, up to [`MAX_POSTPONED_SIZE`] bytes, so they are stored even if
+/// the follow bringing their author into the scope comes after them.
+///
+/// [`MAX_POSTPONED_SIZE`]: constant.MAX_POSTPONED_SIZE.html
+pub fn import<R>(storage_path: &Path, format: Format, scope: Scope, reader: R) -> Result<Imported>
+    where R: BufRead
+{
+    let mut importer = Importer::new(storage_path, scope)?;
+    importer.read(format, reader)?;
+    Ok(importer.finish())
+}
+
+/// Total size of the frames an [`import`] keeps for the authors out of its scope,
+/// the frames above it are ignored
+///
+/// [`import`]: fn.import.html
+pub const MAX_POSTPONED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Reads the next line of at most `limit` bytes, `None` at the end of the input
+///
+/// A longer line is rejected without reading the rest of it.
+fn read_line<R>(reader: &mut R, limit: u64) -> Result<Option<String>>
+    where R: BufRead
+{
+    let mut line = Vec::new();
+    reader.take(limit + 1).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    if line.len() as u64 > limit {
+        let kind = CoreErrorKind::LimitExceeded("JSON frame".to_string(), line.len() as u64, limit);
+        return Err(CoreError::from(kind).into());
+    }
+    let line = String::from_utf8(line).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    Ok(Some(line))
+}
+
+struct Importer {
+    store: FeedStore,
+    /// Our named feeds by their author, see `feeds::named_authors`
+    named: HashMap<PubKey, String>,
+    feeds: HashMap<PubKey, AuthorFeed>,
+    graph: Option<ScopeGraph>,
+    /// Frames waiting to be imported, the read one and the postponed ones it released
+    queue: VecDeque<Frame>,
+    imported: Imported,
+}
+
+/// The follow graph deciding which authors are in a `Scope::Hops`
+struct ScopeGraph {
+    graph: SocialGraph,
+    roots: Vec<PubKey>,
+    max_hops: u32,
+    wanted: BTreeMap<PubKey, u32>,
+    /// Frames of the authors out of the scope, in case a later follow brings them in
+    postponed: HashMap<PubKey, Vec<Frame>>,
+    /// Encoded size of the postponed frames
+    postponed_size: u64,
+    max_postponed_size: u64,
+}
+
+/// The stored feed of an author, with the state needed to extend it
+struct AuthorFeed {
+    feed: Feed,
+    validator: FeedValidator,
+    stored: HashSet<MessageId>,
+}
+
+impl Importer {
+    fn new(storage_path: &Path, scope: Scope) -> Result<Importer>
+    {
+        let store = feeds::open_store(storage_path)?;
+        let named = feeds::named_authors(&store)?;
+        let graph = match scope {
+            Scope::All => None,
+            Scope::Hops(max_hops) => {
+                let roots = social::own_authors(storage_path)?;
+                let graph = social::load(&store, &named)?;
+                let wanted = graph.hops(&roots, max_hops);
+                Some(ScopeGraph {
+                    graph,
+                    roots,
+                    max_hops,
+                    wanted,
+                    postponed: HashMap::new(),
+                    postponed_size: 0,
+                    max_postponed_size: MAX_POSTPONED_SIZE,
+                })
+            },
+        };
+        Ok(Importer {
+            store,
+            named,
+            feeds: HashMap::new(),
+            graph,
+            queue: VecDeque::new(),
+            imported: Imported::default(),
+        })
+    }
+
+    fn read<R>(&mut self, format: Format, mut reader: R) -> Result<()>
+        where R: BufRead
+    {
+        match format {
+            Format::Binary => {
+                for frame in FrameReader::new(reader) {
+                    self.add(frame?)?;
+                }
+            },
+            Format::Json => {
+                let max_line_len = json::max_text_len(&DecodeLimits::default());
+                while let Some(line) = read_line(&mut reader, max_line_len)? {
+                    if !line.trim().is_empty() {
+                        self.add(json::from_str(&line)?)?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// The outcome, the frames still postponed are ignored
+    fn finish(mut self) -> Imported
+    {
+        if let Some(ref scope) = self.graph {
+            self.imported.ignored += scope.postponed.values().map(|frames| frames.len() as u64).sum::<u64>();
+        }
+        self.imported
+    }
+
+    /// Imports the `frame`, then the postponed frames it brings into the scope
+    fn add(&mut self, frame: Frame) -> Result<()>
+    {
+        self.queue.push_back(frame);
+        while let Some(frame) = self.queue.pop_front() {
+            self.import_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    fn import_frame(&mut self, frame: Frame) -> Result<()>
+    {
+        let message = frame.decode_message()?;
+        let author = message.author;
+        if let Some(ref mut scope) = self.graph {
+            if !scope.wanted.contains_key(&author) {
+                let size = frame.encoded_len() as u64;
+                if scope.postponed_size + size > scope.max_postponed_size {
+                    self.imported.ignored += 1;
+                } else {
+                    scope.postponed_size += size;
+                    scope.postponed.entry(author).or_insert_with(Vec::new).push(frame);
+                }
+                return Ok(());
+            }
+        }
+        if !self.feeds.contains_key(&author) {
+            let author_feed = self.open(&author)?;
+            self.feeds.insert(author, author_feed);
+        }
+        let author_feed = self.feeds.get_mut(&author).expect("unreachable");
+
+        let id = frame.message_id();
+        if author_feed.stored.contains(&id) {
+            self.imported.skipped += 1;
+            return Ok(());
+        }
+        author_feed.validator.validate(&frame)?;
+        author_feed.feed.append(&frame)?;
+        author_feed.stored.insert(id);
+        self.imported.appended += 1;
+
+        if let Some(ref mut scope) = self.graph {
+            if scope.graph.apply_message(&message) {
+                scope.wanted = scope.graph.hops(&scope.roots, scope.max_hops);
+                let released: Vec<PubKey> = scope.postponed.keys()
+                    .filter(|author| scope.wanted.contains_key(author))
+                    .cloned()
+                    .collect();
+                for author in released {
+                    for frame in scope.postponed.remove(&author).unwrap_or_default() {
+                        scope.postponed_size -= frame.encoded_len() as u64;
+                        self.queue.push_back(frame);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the stored feed of the author, a named feed of ours is extended in place
+    fn open(&self, author: &PubKey) -> Result<AuthorFeed>
+    {
+        let feed = match feeds::feed_in(&self.store, &self.named, author)? {
+            Some(feed) => feed,
+            None => self.store.feed(author)?,
+        };
+        let mut stored = HashSet::new();
+        for frame in feed.frames()? {
+            stored.insert(frame?.message_id());
+        }
+        let validator = match feed.head()? {
+            Some(head) => {
+                let stamped = head.decode_message()?.stamp.is_some();
+                FeedValidator::resume(*author, head.message_id(), feed.len()?, stamped)
+            },
+            None => FeedValidator::new(),
+        };
+        Ok(AuthorFeed { feed, validator, stored })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use kutyus_core::message::ContentType;
+    use ::keys;
+
+    #[test]
+    fn exported_feed_can_be_imported_in_both_formats()
+    {
+        for &format in &[Format::Binary, Format::Json] {
+            let (source, exported) = exported_feed(format, 3);
+
+            let target = TempDir::new("transfer").unwrap();
+            let imported = import(target.path(), format, Scope::All, io::Cursor::new(exported)).unwrap();
+            assert_eq!(imported, Imported { appended: 3, skipped: 0, ignored: 0 });
+
+            let (source_feed, keypair) = feeds::open_default(source.path()).unwrap();
+            let target_feed = feeds::open_store(target.path()).unwrap()
+                .feed(&PubKey::from_keypair(&keypair)).unwrap();
+            let source_frames: Vec<Vec<u8>> = source_feed.frames().unwrap().map(|f| f.unwrap().message).collect();
+            let target_frames: Vec<Vec<u8>> = target_feed.frames().unwrap().map(|f| f.unwrap().message).collect();
+            assert_eq!(source_frames, target_frames);
+        }
+    }
+
+    #[test]
+    fn already_stored_frames_are_skipped()
+    {
+        let (_source, exported) = exported_feed(Format::Json, 2);
+        let target = TempDir::new("transfer").unwrap();
+        import(target.path(), Format::Json, Scope::All, io::Cursor::new(exported.clone())).unwrap();
+
+        let imported = import(target.path(), Format::Json, Scope::All, io::Cursor::new(exported)).unwrap();
+        assert_eq!(imported, Imported { appended: 0, skipped: 2, ignored: 0 });
+    }
+
+    #[test]
+    fn named_feed_imported_back_is_not_copied()
+    {
+        let storage = TempDir::new("transfer").unwrap();
+        let author = keys::generate(storage.path(), "bot", false).unwrap();
+        feeds::create(storage.path(), "news", "bot").unwrap();
+        let (feed, _) = feeds::open(storage.path(), "news").unwrap();
+
+        let mut exported = Vec::new();
+        let length = export(&feed, Format::Binary, &mut exported).unwrap();
+        let imported = import(storage.path(), Format::Binary, Scope::Hops(2), io::Cursor::new(exported)).unwrap();
+
+        assert_eq!(imported, Imported { appended: 0, skipped: length, ignored: 0 });
+        assert!(!feeds::open_store(storage.path()).unwrap().has_feed(&author).unwrap());
+        assert_eq!(feed.len().unwrap(), length);
+    }
+
+    #[test]
+    fn frames_must_continue_the_stored_feed()
+    {
+        let (_source, exported) = exported_feed(Format::Json, 3);
+        let lines: Vec<&[u8]> = exported.split(|&byte| byte == b'\n').collect();
+        // the second message is missing
+        let gap = [lines[0], lines[2]].join(&b'\n');
+
+        let target = TempDir::new("transfer").unwrap();
+        assert!(import(target.path(), Format::Json, Scope::All, io::Cursor::new(gap)).is_err());
+    }
+
+    #[test]
+    fn only_feeds_in_the_scope_are_imported()
+    {
+        use kutyus_core::content;
+        use kutyus_core::content::contact::{Contact, Relation};
+
+        let (source, exported) = exported_feed(Format::Binary, 2);
+        let source_author = PubKey::from_keypair(&feeds::open_default(source.path()).unwrap().1);
+        let target = TempDir::new("transfer").unwrap();
+        keys::generate(target.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+
+        let imported = import(target.path(), Format::Binary, Scope::Hops(1), io::Cursor::new(exported.clone())).unwrap();
+        assert_eq!(imported, Imported { appended: 0, skipped: 0, ignored: 2 });
+
+        let (feed, keypair) = feeds::open_default(target.path()).unwrap();
+        let (content_type, content) = content::encode(&Contact { contact: source_author, relation: Relation::Follow }).unwrap();
+        feeds::append(&feed, &keypair, content_type, content).unwrap();
+
+        let imported = import(target.path(), Format::Binary, Scope::Hops(1), io::Cursor::new(exported)).unwrap();
+        assert_eq!(imported, Imported { appended: 2, skipped: 0, ignored: 0 });
+    }
+
+    #[test]
+    fn frames_before_the_follow_of_their_author_are_imported()
+    {
+        let (_sources, friend_author, middle_author, exported) = friend_before_follow();
+        let target = TempDir::new("transfer").unwrap();
+        keys::generate(target.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        follow(target.path(), &middle_author);
+
+        let imported = import(target.path(), Format::Binary, Scope::Hops(2), io::Cursor::new(exported)).unwrap();
+        assert_eq!(imported, Imported { appended: 3, skipped: 0, ignored: 0 });
+        assert_eq!(feeds::open_store(target.path()).unwrap().feed(&friend_author).unwrap().len().unwrap(), 2);
+    }
+
+    #[test]
+    fn postponed_frames_above_the_limit_are_ignored()
+    {
+        let (_sources, friend_author, middle_author, exported) = friend_before_follow();
+        let target = TempDir::new("transfer").unwrap();
+        keys::generate(target.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        follow(target.path(), &middle_author);
+
+        let mut importer = Importer::new(target.path(), Scope::Hops(2)).unwrap();
+        let first = FrameReader::new(io::Cursor::new(&exported)).next().unwrap().unwrap();
+        importer.graph.as_mut().unwrap().max_postponed_size = first.encoded_len() as u64;
+        importer.read(Format::Binary, io::Cursor::new(exported)).unwrap();
+
+        assert_eq!(importer.finish(), Imported { appended: 2, skipped: 0, ignored: 1 });
+        assert_eq!(feeds::open_store(target.path()).unwrap().feed(&friend_author).unwrap().len().unwrap(), 1);
+    }
+
+    #[test]
+    fn overlong_json_line_is_rejected()
+    {
+        let (source, mut exported) = exported_feed(Format::Json, 1);
+        let limit = json::max_text_len(&DecodeLimits::default()) as usize;
+        exported.extend(vec![b' '; limit + 1]);
+
+        let target = TempDir::new("transfer").unwrap();
+        assert!(import(target.path(), Format::Json, Scope::All, io::Cursor::new(exported)).is_err());
+        // the frames before the long line are kept
+        let author = PubKey::from_keypair(&feeds::open_default(source.path()).unwrap().1);
+        assert_eq!(feeds::open_store(target.path()).unwrap().feed(&author).unwrap().len().unwrap(), 1);
+
+        let mut lines = io::Cursor::new(b"abc\nabcd\n".to_vec());
+        assert_eq!(read_line(&mut lines, 3).unwrap(), Some("abc".to_string()));
+        assert!(read_line(&mut lines, 3).is_err());
+    }
+
+    #[test]
+    fn unknown_format_is_rejected()
+    {
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    /// Appends a follow of `contact` to the default feed
+    fn follow(storage: &Path, contact: &PubKey) -> Feed
+    {
+        use kutyus_core::content;
+        use kutyus_core::content::contact::{Contact, Relation};
+
+        let (feed, keypair) = feeds::open_default(storage).unwrap();
+        let (content_type, content) = content::encode(&Contact { contact: *contact, relation: Relation::Follow }).unwrap();
+        feeds::append(&feed, &keypair, content_type, content).unwrap();
+        feed
+    }
+
+    /// Two frames of a friend, followed by the feed of a middle author following the friend
+    ///
+    /// Returns the storages, the friend, the middle author and the exported frames.
+    fn friend_before_follow() -> (Vec<TempDir>, PubKey, PubKey, Vec<u8>)
+    {
+        let (friend, mut exported) = exported_feed(Format::Binary, 2);
+        let friend_author = PubKey::from_keypair(&feeds::open_default(friend.path()).unwrap().1);
+        let middle = TempDir::new("transfer").unwrap();
+        let middle_author = keys::generate(middle.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let middle_feed = follow(middle.path(), &friend_author);
+        export(&middle_feed, Format::Binary, &mut exported).unwrap();
+        (vec![friend, middle], friend_author, middle_author, exported)
+    }
+
+    fn exported_feed(format: Format, length: u8) -> (TempDir, Vec<u8>)
+    {
+        let source = TempDir::new("transfer").unwrap();
+        keys::generate(source.path(), keys::DEFAULT_KEY_NAME, false).unwrap();
+        let (feed, keypair) = feeds::open_default(source.path()).unwrap();
+        for n in 0..length {
+            feeds::append(&feed, &keypair, ContentType::Blob, vec![n]).unwrap();
+        }
+
+        let mut exported = Vec::new();
+        assert_eq!(export(&feed, format, &mut exported).unwrap(), u64::from(length));
+        (source, exported)
+    }
+}